@@ -2,25 +2,67 @@ use std::{
     convert::TryInto,
     collections::HashMap,
 };
-use rand::{rngs::StdRng, SeedableRng, Rng};
 use num::rational::Rational;
 #[macro_use]
 extern crate pest_derive;
 
+#[macro_export]
+macro_rules! pattern {
+    ($inner:expr) => {
+        $crate::Pattern::new($inner)
+    }
+}
+
 pub mod mini_notation;
 pub mod sound;
+pub mod params;
+pub mod operators;
+pub use operators::{Structure, Operand, add, sub, mul, div, set, keep, merge};
+pub use params::{
+    s, n, note, speed, gain, pan, shape, cutoff, resonance, room, size, orbit, legato, sustain,
+    begin, end, crush, coarse, vowel, accelerate, velocity, midichan, ccn, ccv, cps,
+};
 
 pub type Time = Rational;
-pub type Pattern<A> = std::sync::Arc<dyn Fn(Arc) -> Vec<Event<A>> + Send + Sync>;
+/// A function from a time span to the events active during it. Patterns can be
+/// queried by calling them like functions.
+pub struct Pattern<A>(std::sync::Arc<dyn Fn(Arc) -> Vec<Event<A>> + Send + Sync>);
+
+impl<A> Pattern<A> {
+    pub fn new(query: impl Fn(Arc) -> Vec<Event<A>> + Send + Sync + 'static) -> Self {
+        Pattern(std::sync::Arc::new(query))
+    }
+
+    pub fn query(&self, arc: Arc) -> Vec<Event<A>> {
+        (self.0)(arc)
+    }
+}
+
+impl<A> Clone for Pattern<A> {
+    fn clone(&self) -> Self {
+        Pattern(self.0.clone())
+    }
+}
 
-#[derive(Copy, Clone, Debug)]
+impl<A> std::ops::Deref for Pattern<A> {
+    type Target = dyn Fn(Arc) -> Vec<Event<A>> + Send + Sync;
+
+    fn deref(&self) -> &Self::Target {
+        &*self.0
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct Arc {
     pub start: Rational,
     pub stop: Rational,
 }
 
 pub struct Event<A> {
+    /// The full extent of the event, or `None` for events from continuous
+    /// patterns such as `sine`, which have no start or end of their own.
     pub whole: Option<Arc>,
+    /// The fragment of the event within the span that was queried.
     pub part: Arc,
     pub value: A,
 }
@@ -33,16 +75,13 @@ impl<A> Event<A> {
             self.part
         }
     }
-}
 
-#[macro_export]
-macro_rules! pattern {
-    ($inner:expr) => {
-        std::sync::Arc::new($inner)
+    /// True if this is the fragment of the event which contains its start.
+    pub fn has_onset(&self) -> bool {
+        self.whole.is_some_and(|w| w.start == self.part.start)
     }
 }
 
-
 impl<A: std::fmt::Debug> std::fmt::Debug for Event<A> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Event")
@@ -54,13 +93,24 @@ impl<A: std::fmt::Debug> std::fmt::Debug for Event<A> {
 }
 
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Value {
     String(String),
     Integer(isize),
     Float(f32),
 }
 
+impl Value {
+    /// The value as a number, if it is one.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Integer(v) => Some(*v as f64),
+            Value::Float(v) => Some(*v as f64),
+            Value::String(_) => None,
+        }
+    }
+}
+
 impl TryInto<String> for Value {
     type Error = ();
     fn try_into(self) -> Result<String, Self::Error> {
@@ -91,7 +141,7 @@ impl TryInto<f32> for Value {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct ControlMap(pub HashMap<String, Value>);
 
 
@@ -121,11 +171,11 @@ pub fn sound(p: Pattern<String>) -> Pattern<ControlMap> {
             let mut m = ControlMap(HashMap::new());
             let parts:Vec<_> = e.value.split(":").collect();
             m.0.insert("s".to_string(), Value::String(parts[0].to_string()));
-            if parts.len() > 1 {
-                m.0.insert("n".to_string(), Value::Integer(parts[1].parse().unwrap()));
-            } else {
-                m.0.insert("n".to_string(), Value::Integer(0));
-            }
+            let n = match parts.get(1) {
+                Some(n) => n.parse().map(Value::Integer).or_else(|_| n.parse().map(Value::Float)).unwrap_or(Value::Integer(0)),
+                None => Value::Integer(0),
+            };
+            m.0.insert("n".to_string(), n);
             Event {
                 whole: e.whole,
                 part: e.part,
@@ -134,47 +184,56 @@ pub fn sound(p: Pattern<String>) -> Pattern<ControlMap> {
         }).collect()
     })
 }
-pub fn pan(p: Pattern<f32>) -> Pattern<ControlMap> {
+
+/// Combines the values of two patterns, keeping the structure of the left one.
+/// Each left event is matched with every right event that overlaps its whole,
+/// giving a fragment of the left event for each overlap with its part.
+pub fn apply_from_left<A: 'static + Clone, B: 'static + Clone, C: 'static>(f: impl Fn(A, B) -> C + Send + Sync + 'static, lhs: Pattern<A>, rhs: Pattern<B>) -> Pattern<C> {
     pattern!(move |arc| {
-        p(arc).into_iter().map(|e| {
-            let mut m = ControlMap(HashMap::new());
-            m.0.insert("pan".to_string(), Value::Float(e.value));
-            Event {
-                whole: e.whole,
-                part: e.part,
-                value: m,
+        let mut results = vec![];
+        for l in lhs(arc) {
+            for r in rhs(l.whole_or_part()) {
+                if let Some(part) = sub_arc(l.part, r.part) {
+                    results.push(Event {
+                        whole: l.whole,
+                        part,
+                        value: f(l.value.clone(), r.value),
+                    });
+                }
             }
-        }).collect()
+        }
+        results
     })
 }
 
-pub fn apply_from_left<A: 'static + Clone, B: 'static + Clone>(f: fn(A, B) -> A, lhs: Pattern<A>, rhs: Pattern<B>) -> Pattern<A> {
+/// Combines the values of two patterns, keeping the structure of the right one.
+pub fn apply_from_right<A: 'static + Clone, B: 'static + Clone, C: 'static>(f: impl Fn(A, B) -> C + Send + Sync + 'static, lhs: Pattern<A>, rhs: Pattern<B>) -> Pattern<C> {
+    apply_from_left(move |b, a| f(a, b), rhs, lhs)
+}
+
+/// Combines the values of two patterns wherever their events overlap, taking
+/// structure from both.
+pub fn apply_both<A: 'static + Clone, B: 'static + Clone, C: 'static>(f: impl Fn(A, B) -> C + Send + Sync + 'static, lhs: Pattern<A>, rhs: Pattern<B>) -> Pattern<C> {
     pattern!(move |arc| {
-        let mut lhs:Vec<Event<A>> = lhs(arc);
-        let mut rhs = rhs(arc);
+        let rhs = rhs(arc);
         let mut results = vec![];
-        if lhs.len() == 0 || rhs.len() == 0 {
-            return results;
-        }
-
-        let mut current_r:Option<Event<B>> = None;
-        for current_l in &lhs {
-            if current_r.is_some() {
-                if current_r.as_ref().unwrap().part.start > current_l.part.stop {
-                    current_r = None;
-                }
-            }
-            while !rhs.is_empty() {
-                if rhs[0].part.start > current_l.part.start {
-                    break;
-                }
-                current_r = Some(rhs.remove(0));
-            }
-            if let Some(current_r) = current_r.as_ref() {
+        for l in lhs(arc) {
+            for r in &rhs {
+                let part = match sub_arc(l.part, r.part) {
+                    Some(part) => part,
+                    None => continue,
+                };
+                let whole = match (l.whole, r.whole) {
+                    (Some(a), Some(b)) => match sub_arc(a, b) {
+                        Some(whole) => Some(whole),
+                        None => continue,
+                    },
+                    _ => None,
+                };
                 results.push(Event {
-                    whole: current_l.whole,
-                    part: current_l.part,
-                    value: f(current_l.value.clone(), current_r.value.clone()),
+                    whole,
+                    part,
+                    value: f(l.value.clone(), r.value.clone()),
                 });
             }
         }
@@ -182,11 +241,10 @@ pub fn apply_from_left<A: 'static + Clone, B: 'static + Clone>(f: fn(A, B) -> A,
     })
 }
 
-pub fn jux_by(n: Pattern<f32>, f: fn(Pattern<ControlMap>) -> Pattern<ControlMap>, p: Pattern<ControlMap>) -> Pattern<ControlMap> {
+pub fn jux_by(n: Pattern<f32>, f: impl Fn(Pattern<ControlMap>) -> Pattern<ControlMap>, p: Pattern<ControlMap>) -> Pattern<ControlMap> {
     stack(vec![
         apply_from_left(
-            |state, pan| {
-                let mut state = state.clone();
+            |mut state: ControlMap, pan: f32| {
                 state.0.insert("pan".to_string(), Value::Float(pan));
                 state
             },
@@ -194,8 +252,7 @@ pub fn jux_by(n: Pattern<f32>, f: fn(Pattern<ControlMap>) -> Pattern<ControlMap>
             n.clone()
         ),
         apply_from_left(
-            |state, pan| {
-                let mut state = state.clone();
+            |mut state: ControlMap, pan: f32| {
                 state.0.insert("pan".to_string(), Value::Float(1.0-pan));
                 state
             },
@@ -207,16 +264,14 @@ pub fn jux_by(n: Pattern<f32>, f: fn(Pattern<ControlMap>) -> Pattern<ControlMap>
 
 pub fn sect(a: Arc, b: Arc) -> Arc {
     Arc {
-        start: a.start.min(b.start),
-        stop: a.stop.max(b.stop),
+        start: a.start.max(b.start),
+        stop: a.stop.min(b.stop),
     }
 }
 
 pub fn sub_arc(a: Arc, b: Arc) -> Option<Arc> {
     let c = sect(a, b);
-    if c.start == c.stop && c.start == a.stop && a.start < a.stop {
-        None
-    } else if c.start == c.stop && c.start == b.stop && b.start < b.stop {
+    if c.start == c.stop && ((c.start == a.stop && a.start < a.stop) || (c.start == b.stop && b.start < b.stop)) {
         None
     } else if c.start <= c.stop {
         Some(c)
@@ -248,7 +303,7 @@ pub fn superimpose<A: 'static>(f: impl Fn(&Pattern<A>) -> Pattern<A>, p: Pattern
 fn with_result_arc<A: 'static>(f: impl Fn(Arc)->Arc + Send + Sync + 'static, p: Pattern<A>) -> Pattern<A> {
     pattern!(move |arc| {
         p(arc).into_iter().map(|e| Event {
-            whole: e.whole.map(|w| f(w)),
+            whole: e.whole.map(&f),
             part: f(e.part),
             value: e.value,
         }).collect()
@@ -297,15 +352,159 @@ pub fn off<A: 'static>(tp: Pattern<Time>, f: impl Fn(&Pattern<A>) -> Pattern<A>
 
 
 
-fn time_rand(t: Time) -> f32 {
-    let x = (t*t) / 1000000;
-    //TODO: Uh. Understand what this does in a bunch of cases. It's probably pretty "random" sometimes
-    // Also it obviously throws away information when the numbers are large but they normally won't be so maybe that doesn't matter. Also this is not crypto and not a place where pattern artifacts will be very visible.
-    let mut seed = [0; 32];
-    seed[0] = *x.numer() as u8;
-    seed[1] = *x.denom() as u8;
-    let mut rng:StdRng = StdRng::from_seed(seed);
-    rng.gen()
+pub(crate) fn time_to_f64(t: Time) -> f64 {
+    *t.numer() as f64 / *t.denom() as f64
+}
+
+/// Tidal's xorshift step.
+fn xorwise(x: i64) -> i64 {
+    let a = (x << 13) ^ x;
+    let b = (a >> 17) ^ a;
+    (b << 5) ^ b
+}
+
+/// Stretches 300 cycles over the range [0, 2^29) and applies `xorwise`, like
+/// Tidal's `timeToIntSeed`.
+fn time_to_int_seed(t: f64) -> i64 {
+    let x = t / 300.0;
+    xorwise(((x - x.trunc()) * 536870912.0).trunc() as i64)
+}
+
+fn int_seed_to_rand(seed: i64) -> f64 {
+    seed.rem_euclid(536870912) as f64 / 536870912.0
+}
+
+/// A pseudo-random number from 0 to 1 for a point in time, identical to Tidal's `timeToRand`.
+fn time_rand(t: Time) -> f64 {
+    int_seed_to_rand(time_to_int_seed(time_to_f64(t)))
+}
+
+/// A continuous pattern of pseudo-random numbers from 0 to 1. Each value is
+/// chosen by the middle of the span it's queried over, so the same query
+/// always gives the same number, and the same one as Tidal's `rand`.
+pub fn rand() -> Pattern<f32> {
+    pattern!(|arc: Arc| {
+        vec![Event {
+            whole: None,
+            part: arc,
+            value: time_rand((arc.start + arc.stop) / 2) as f32,
+        }]
+    })
+}
+
+fn _irand(n: isize) -> Pattern<isize> {
+    pattern!(move |arc: Arc| {
+        vec![Event {
+            whole: None,
+            part: arc,
+            value: (time_rand((arc.start + arc.stop) / 2) * n as f64).floor() as isize,
+        }]
+    })
+}
+
+/// A continuous pattern of pseudo-random integers from 0 up to but not including `n`.
+pub fn irand(n: Pattern<isize>) -> Pattern<isize> {
+    t_param(|n, ()| _irand(n), n, ())
+}
+
+/// A continuous pattern whose value is `f` of the middle of the span it's
+/// queried over. Its events have no whole, so they never have onsets and won't
+/// be played directly; use `segment` or combine it with a discrete pattern
+/// to sample it.
+pub fn sig<A: 'static>(f: impl Fn(Time) -> A + Send + Sync + 'static) -> Pattern<A> {
+    pattern!(move |arc: Arc| {
+        if arc.start > arc.stop {
+            vec![]
+        } else {
+            vec![Event {
+                whole: None,
+                part: arc,
+                value: f((arc.start + arc.stop) / 2),
+            }]
+        }
+    })
+}
+
+/// A sine wave from 0 to 1 with a period of one cycle.
+pub fn sine() -> Pattern<f32> {
+    sig(|t| ((std::f64::consts::TAU * time_to_f64(t)).sin() as f32 + 1.0) / 2.0)
+}
+
+/// `sine` a quarter of a cycle later.
+pub fn cosine() -> Pattern<f32> {
+    rot_r(Rational::new(1, 4), sine())
+}
+
+/// Rises from 0 to 1 over each cycle.
+pub fn saw() -> Pattern<f32> {
+    sig(|t| time_to_f64(cycle_pos(t)) as f32)
+}
+
+/// Falls from 1 to 0 over each cycle.
+pub fn isaw() -> Pattern<f32> {
+    sig(|t| 1.0 - time_to_f64(cycle_pos(t)) as f32)
+}
+
+/// Rises from 0 to 1 over the first half of each cycle and falls back over the second.
+pub fn tri() -> Pattern<f32> {
+    fastcat(vec![saw(), isaw()])
+}
+
+/// 0 for the first half of each cycle and 1 for the second.
+pub fn square() -> Pattern<f32> {
+    sig(|t| if cycle_pos(t) < Rational::new(1, 2) { 0.0 } else { 1.0 })
+}
+
+/// Rises from 0 to 1 over the first cycle and stays there.
+pub fn env_l() -> Pattern<f32> {
+    sig(|t| time_to_f64(t).clamp(0.0, 1.0) as f32)
+}
+
+/// Smooth noise from 0 to 1, interpolating between a random value at the start
+/// of each cycle. The same as Tidal's `perlin`.
+pub fn perlin() -> Pattern<f32> {
+    sig(|t| {
+        let a = time_rand(sam(t));
+        let b = time_rand(sam(t) + 1);
+        let x = time_to_f64(cycle_pos(t));
+        let smoother_step = 6.0 * x.powi(5) - 15.0 * x.powi(4) + 10.0 * x.powi(3);
+        (a + smoother_step * (b - a)) as f32
+    })
+}
+
+/// Scales a pattern of values from 0 to 1 so that they run from `from` to `to`,
+/// keeping the structure of `p`.
+pub fn range(from: Pattern<f32>, to: Pattern<f32>, p: Pattern<f32>) -> Pattern<f32> {
+    apply_from_right(
+        |(from, to): (f32, f32), v: f32| from + (to - from) * v,
+        apply_both(|from, to| (from, to), from, to),
+        p,
+    )
+}
+
+/// Like `range` but rounds down to integers, so values from 0 up to but not
+/// including 1 give integers from `from` up to `to` - 1.
+pub fn irange(from: Pattern<isize>, to: Pattern<isize>, p: Pattern<f32>) -> Pattern<isize> {
+    apply_from_right(
+        |(from, to): (isize, isize), v: f32| from + ((to - from) as f32 * v).floor() as isize,
+        apply_both(|from, to| (from, to), from, to),
+        p,
+    )
+}
+
+fn _segment<A: 'static + Clone>(n: Time, p: Pattern<A>) -> Pattern<A> {
+    apply_from_left(|_, v| v, _fast(n, unit(())), p)
+}
+
+/// Samples the pattern `n` times per cycle, turning continuous patterns into
+/// discrete events.
+pub fn segment<A: 'static + Clone>(n: Pattern<Time>, p: Pattern<A>) -> Pattern<A> {
+    t_param(_segment, n, p)
+}
+
+/// The same as `segment`.
+pub fn discretise<A: 'static + Clone>(n: Pattern<Time>, p: Pattern<A>) -> Pattern<A> {
+    segment(n, p)
 }
 
 pub fn overlay<A: 'static>(a: Pattern<A>, b: Pattern<A>) -> Pattern<A> {
@@ -326,15 +525,36 @@ fn t_param<A: 'static, T1: 'static, T2: 'static + Clone + Send + Sync>(f: impl F
     }))
 }
 
-fn _degrade_by<A: 'static>(prob: f32, p: Pattern<A>) -> Pattern<A> {
+fn t_param2<A: 'static, T1: 'static + Clone + Send + Sync, T2: 'static, P: 'static + Clone + Send + Sync>(f: impl Fn(T1, T2, P) -> Pattern<A> + Send + Sync + 'static, tv1: Pattern<T1>, tv2: Pattern<T2>, p: P) -> Pattern<A> {
+    let f = std::sync::Arc::new(f);
+    t_param(move |v1, (tv2, p)| {
+        let f = f.clone();
+        t_param(move |v2, p| f(v1.clone(), v2, p), tv2, p)
+    }, tv1, (tv2, p))
+}
+
+fn t_param3<A: 'static, T1: 'static + Clone + Send + Sync, T2: 'static + Clone + Send + Sync, T3: 'static, P: 'static + Clone + Send + Sync>(f: impl Fn(T1, T2, T3, P) -> Pattern<A> + Send + Sync + 'static, tv1: Pattern<T1>, tv2: Pattern<T2>, tv3: Pattern<T3>, p: P) -> Pattern<A> {
+    let f = std::sync::Arc::new(f);
+    t_param(move |v1, (tv2, tv3, p)| {
+        let f = f.clone();
+        t_param2(move |v2, v3, p| f(v1.clone(), v2, v3, p), tv2, tv3, p)
+    }, tv1, (tv2, tv3, p))
+}
+
+/// Keeps the events for which `prand`, queried over the event's whole, is at
+/// least `prob`. Tidal's `_degradeByUsing`.
+fn _degrade_by_using<A: 'static>(prand: Pattern<f32>, prob: f32, p: Pattern<A>) -> Pattern<A> {
     pattern!(move |arc| {
-        p(arc).into_iter().filter(move |e| {
-            let draw = time_rand((arc.start + arc.stop)/2isize);
-            draw > prob
+        p(arc).into_iter().filter(|e| {
+            prand(e.whole_or_part()).first().is_some_and(|r| r.value >= prob)
         }).collect()
     })
 }
 
+fn _degrade_by<A: 'static>(prob: f32, p: Pattern<A>) -> Pattern<A> {
+    _degrade_by_using(rand(), prob, p)
+}
+
 pub fn degrade_by<A: 'static>(prob: Pattern<f32>, p: Pattern<A>) -> Pattern<A> {
     t_param(|prob, p| {
         _degrade_by(prob, p)
@@ -344,8 +564,7 @@ pub fn degrade_by<A: 'static>(prob: Pattern<f32>, p: Pattern<A>) -> Pattern<A> {
 fn _undegrade_by<A: 'static>(prob: f32, p: Pattern<A>) -> Pattern<A> {
     pattern!(move |arc| {
         p(arc).into_iter().filter(|e| {
-            let draw = time_rand((arc.start + arc.stop)/2isize);
-            draw <= prob
+            rand()(e.whole_or_part()).first().is_some_and(|r| r.value < prob)
         }).collect()
     })
 }
@@ -361,32 +580,32 @@ pub fn filter_values<A: 'static>(f: impl Fn(&A) -> bool + Send + Sync + 'static,
 }
 
 pub fn sometimes_by<A: 'static>(x: Pattern<f32>, f: impl Fn(Pattern<A>) -> Pattern<A> + Send + Sync, p: Pattern<A>) -> Pattern<A> {
-    overlay(degrade_by(x.clone(), p.clone()), undegrade_by(x, f(p)))
+    overlay(degrade_by(x.clone(), p.clone()), f(undegrade_by(x, p)))
 }
 
+pub fn silence<A: 'static>() -> Pattern<A> {
+    pattern!(|_| vec![])
+}
 
 pub fn unit<A: Clone + Sync + Send + 'static>(v: A) -> Pattern<A> {
     pattern!(move |arc| {
-        arc_cycles_zw(arc).into_iter().filter_map(|a| {
-            if arc.start.is_integer() {
-                Some(Event {
-                    whole: Some(Arc { start: a.start, stop: a.start + 1 }),
-                    part: Arc { start: a.start, stop: (a.start + 1).min(a.stop) },
-                    value: v.clone(),
-                })
-            } else { None }
+        arc_cycles_zw(arc).into_iter().map(|a| {
+            Event {
+                whole: Some(Arc { start: sam(a.start), stop: sam(a.start) + 1 }),
+                part: a,
+                value: v.clone(),
+            }
         }).collect()
     })
 }
 
-pub fn fast<A: 'static>(r: Time, p: Pattern<A>) -> Pattern<A> {
-    pattern!(move |arc| {
-        if r == 0.into() {
-            vec![]
-        } else if r < 0.into() {
-            todo!()
-            //rev(arc, |arc| fast(arc, -r, p))
-        } else {
+fn _fast<A: 'static>(r: Time, p: Pattern<A>) -> Pattern<A> {
+    if r == 0.into() {
+        silence()
+    } else if r < 0.into() {
+        rev(_fast(-r, p))
+    } else {
+        pattern!(move |arc| {
             let arc = Arc {
                 start: arc.start * r,
                 stop: arc.stop * r,
@@ -404,15 +623,48 @@ pub fn fast<A: 'static>(r: Time, p: Pattern<A>) -> Pattern<A> {
                     value: e.value,
                 }
             }).collect()
-        }
-    })
+        })
+    }
+}
+
+/// Speeds the pattern up by `r`. A rate of zero gives silence and negative rates
+/// also reverse the pattern.
+pub fn fast<A: 'static>(r: Pattern<Time>, p: Pattern<A>) -> Pattern<A> {
+    t_param(_fast, r, p)
+}
+
+fn _slow<A: 'static>(r: Time, p: Pattern<A>) -> Pattern<A> {
+    if r == 0.into() {
+        silence()
+    } else {
+        _fast(r.recip(), p)
+    }
+}
+
+/// Slows the pattern down by `r`. See `fast`.
+pub fn slow<A: 'static>(r: Pattern<Time>, p: Pattern<A>) -> Pattern<A> {
+    t_param(_slow, r, p)
+}
+
+/// Speeds the pattern up like `fast` and multiplies its `speed` by the same
+/// amount, so samples play faster too.
+pub fn hurry(r: Pattern<Time>, p: Pattern<ControlMap>) -> Pattern<ControlMap> {
+    let speeds = r.clone();
+    let speeds = pattern!(move |arc| {
+        speeds(arc).into_iter().map(|e: Event<Time>| Event {
+            whole: e.whole,
+            part: e.part,
+            value: time_to_f64(e.value) as f32,
+        }).collect()
+    });
+    mul(Structure::Left, fast(r, p), speed(speeds))
 }
 
 pub fn stack<A: 'static>(ps: Vec<Pattern<A>>) -> Pattern<A> {
-    std::sync::Arc::new(move |arc| {
+    pattern!(move |arc| {
         let mut results = vec![];
         for p in &ps {
-            results.extend(p(arc).into_iter());
+            results.extend(p(arc));
         }
         results.sort_by_key(|e| e.part.start);
         results
@@ -421,7 +673,7 @@ pub fn stack<A: 'static>(ps: Vec<Pattern<A>>) -> Pattern<A> {
 
 pub fn cat<A: 'static>(ps: Vec<Pattern<A>>) -> Pattern<A> {
     let n = ps.len();
-    std::sync::Arc::new(move |arc: Arc| {
+    pattern!(move |arc: Arc| {
         let f = |arc: Arc| {
             let cyc = arc.start.floor();
             let mut i = cyc % n as isize;
@@ -445,10 +697,151 @@ pub fn cat<A: 'static>(ps: Vec<Pattern<A>>) -> Pattern<A> {
                 value: e.value
             })
         };
-        arc_cycles_zw(arc).into_iter().flat_map(|arc| f(arc)).collect()
+        arc_cycles_zw(arc).into_iter().flat_map(f).collect()
     })
 }
 
+pub fn fastcat<A: 'static>(ps: Vec<Pattern<A>>) -> Pattern<A> {
+    let n = ps.len() as isize;
+    _fast(n.into(), cat(ps))
+}
+
+/// Like `fastcat` but each pattern is given a share of the cycle proportional to its weight.
+pub fn timecat<A: 'static>(ps: Vec<(Time, Pattern<A>)>) -> Pattern<A> {
+    let total = ps.iter().fold(Time::from(0), |total, (w, _)| total + w);
+    if total <= 0.into() {
+        return silence();
+    }
+    let mut start = Time::from(0);
+    stack(ps.into_iter().map(|(w, p)| {
+        let stop = start + w;
+        let p = compress(Arc { start: start / total, stop: stop / total }, p);
+        start = stop;
+        p
+    }).collect())
+}
+
+/// Squeezes each cycle of the pattern into the given part of the cycle, leaving silence around it.
+pub fn compress<A: 'static>(a: Arc, p: Pattern<A>) -> Pattern<A> {
+    let zero = Time::from(0);
+    let one = Time::from(1);
+    if a.start >= a.stop || a.start < zero || a.stop > one {
+        silence()
+    } else {
+        rot_r(a.start, fast_gap(one / (a.stop - a.start), p))
+    }
+}
+
+/// Speeds up the pattern but only plays the first cycle's worth of it, leaving a gap at the end of each cycle.
+pub fn fast_gap<A: 'static>(r: Time, p: Pattern<A>) -> Pattern<A> {
+    if r <= 0.into() {
+        return silence();
+    }
+    let r = r.max(1.into());
+    split_queries(pattern!(move |arc: Arc| {
+        let munge = |t: Time| sam(t) + (r * cycle_pos(t)).min(1.into());
+        let query = Arc { start: munge(arc.start), stop: munge(arc.stop) };
+        if query.start == sam(arc.start) + 1 {
+            return vec![];
+        }
+        let unmunge = |a: Arc| {
+            let cycle = sam(a.start);
+            Arc { start: cycle + (a.start - cycle) / r, stop: cycle + (a.stop - cycle) / r }
+        };
+        p(query).into_iter().map(|e| Event {
+            whole: e.whole.map(unmunge),
+            part: unmunge(e.part),
+            value: e.value,
+        }).collect()
+    }))
+}
+
+/// Picks one of the patterns at random for each cycle.
+pub fn cycle_choose<A: 'static>(ps: Vec<Pattern<A>>) -> Pattern<A> {
+    cycle_choose_using(rand(), ps)
+}
+
+/// Like `cycle_choose` but picks using `prand`, queried over each cycle.
+fn cycle_choose_using<A: 'static>(prand: Pattern<f32>, ps: Vec<Pattern<A>>) -> Pattern<A> {
+    if ps.is_empty() {
+        return silence();
+    }
+    pattern!(move |arc| {
+        arc_cycles_zw(arc).into_iter().flat_map(|a| {
+            let cycle = Arc { start: sam(a.start), stop: sam(a.start) + 1 };
+            let draw = prand(cycle).first().map_or(0.0, |r| r.value);
+            let i = (draw * ps.len() as f32) as usize;
+            ps[i.min(ps.len() - 1)](a).into_iter().map(move |e| Event {
+                whole: e.whole.map(|w| sect(w, cycle)),
+                part: e.part,
+                value: e.value,
+            })
+        }).collect()
+    })
+}
+
+/// Distributes `k` onsets as evenly as possible over `n` steps using Bjorklund's algorithm.
+pub fn bjorklund(k: usize, n: usize) -> Vec<bool> {
+    let mut counts = (k, n.saturating_sub(k));
+    let mut xs = vec![vec![true]; counts.0];
+    let mut ys = vec![vec![false]; counts.1];
+    while counts.0.min(counts.1) > 1 {
+        if counts.0 > counts.1 {
+            let rest = xs.split_off(counts.1);
+            xs = xs.into_iter().zip(ys).map(|(mut x, y)| { x.extend(y); x }).collect();
+            ys = rest;
+            counts = (counts.1, counts.0 - counts.1);
+        } else {
+            let rest = ys.split_off(counts.0);
+            xs = xs.into_iter().zip(ys).map(|(mut x, y)| { x.extend(y); x }).collect();
+            ys = rest;
+            counts = (counts.0, counts.1 - counts.0);
+        }
+    }
+    xs.into_iter().chain(ys).flatten().collect()
+}
+
+fn _euclid_off<A: 'static>(k: isize, n: isize, r: isize, p: Pattern<A>) -> Pattern<A> {
+    if n <= 0 {
+        return silence();
+    }
+    let steps = bjorklund(k.unsigned_abs(), n as usize).into_iter().map(|b| {
+        if b == (k >= 0) { p.clone() } else { silence() }
+    }).collect();
+    rot_l(Rational::new(r, n), fastcat(steps))
+}
+
+/// Plays `p` on `k` of every `n` steps. Negative `k` plays the off steps instead.
+pub fn euclid<A: 'static>(k: Pattern<isize>, n: Pattern<isize>, p: Pattern<A>) -> Pattern<A> {
+    t_param2(|k, n, p| _euclid_off(k, n, 0, p), k, n, p)
+}
+
+/// Like `euclid` but plays `p` on the steps `euclid` would leave silent.
+pub fn euclid_inv<A: 'static>(k: Pattern<isize>, n: Pattern<isize>, p: Pattern<A>) -> Pattern<A> {
+    t_param2(|k: isize, n, p| _euclid_off(-k, n, 0, p), k, n, p)
+}
+
+/// Plays `p` on the steps `euclid` picks and `p2` on the rest.
+pub fn euclid_full<A: 'static>(k: Pattern<isize>, n: Pattern<isize>, p: Pattern<A>, p2: Pattern<A>) -> Pattern<A> {
+    stack(vec![
+        euclid(k.clone(), n.clone(), p),
+        euclid_inv(k, n, p2),
+    ])
+}
+
+/// Like `euclid` but rotated left by `r` steps.
+pub fn euclid_off<A: 'static>(k: Pattern<isize>, n: Pattern<isize>, r: Pattern<isize>, p: Pattern<A>) -> Pattern<A> {
+    t_param3(_euclid_off, k, n, r, p)
+}
+
+/// Like `euclid_full` but rotated left by `r` steps.
+pub fn euclid_off_full<A: 'static>(k: Pattern<isize>, n: Pattern<isize>, r: Pattern<isize>, p: Pattern<A>, p2: Pattern<A>) -> Pattern<A> {
+    stack(vec![
+        euclid_off(k.clone(), n.clone(), r.clone(), p),
+        t_param3(|k: isize, n, r, p| _euclid_off(-k, n, r, p), k, n, r, p2),
+    ])
+}
+
 pub fn filter_when<A: 'static>(test: impl Fn(Time) -> bool + Clone + Send + Sync + 'static, p: Pattern<A>) -> Pattern<A> {
     pattern!(move |arc| {
         let test = test.clone();
@@ -477,11 +870,140 @@ pub fn within<A: 'static>(a: Arc, f: impl Fn(Pattern<A>)->Pattern<A> + Send + Sy
     ])
 }
 
-pub fn chunk<A: 'static>(n: usize, f: impl Fn(Pattern<A>) -> Pattern<A> + Clone + Send + Sync + 'static, p: Pattern<A>) -> Pattern<A> {
-    cat((0..n-1).map(move |i| {
-        within(Arc { start: (i as isize % n as isize).into(), stop: ((i+1) as isize % n as isize).into() }, f.clone(), p.clone())
-    }).collect()
-   )
+/// Applies `f` to the `i`th of `n` parts of each cycle in turn.
+fn chunk_cycles<A: 'static>(n: isize, order: impl Iterator<Item = isize>, f: impl Fn(Pattern<A>) -> Pattern<A> + Clone + Send + Sync + 'static, p: Pattern<A>) -> Pattern<A> {
+    cat(order.map(|i| {
+        within(Arc { start: Rational::new(i, n), stop: Rational::new(i + 1, n) }, f.clone(), p.clone())
+    }).collect())
+}
+
+fn _chunk<A: 'static>(n: isize, f: impl Fn(Pattern<A>) -> Pattern<A> + Clone + Send + Sync + 'static, p: Pattern<A>) -> Pattern<A> {
+    if n == 0 {
+        p
+    } else if n < 0 {
+        _chunk_back(-n, f, p)
+    } else {
+        chunk_cycles(n, 0..n, f, p)
+    }
+}
+
+/// Divides each cycle into `n` parts and applies `f` to one of them per cycle,
+/// working forwards through the parts. A negative `n` works backwards instead.
+pub fn chunk<A: 'static>(n: Pattern<isize>, f: impl Fn(Pattern<A>) -> Pattern<A> + Clone + Send + Sync + 'static, p: Pattern<A>) -> Pattern<A> {
+    t_param(move |n, p| _chunk(n, f.clone(), p), n, p)
+}
+
+fn _chunk_back<A: 'static>(n: isize, f: impl Fn(Pattern<A>) -> Pattern<A> + Clone + Send + Sync + 'static, p: Pattern<A>) -> Pattern<A> {
+    if n == 0 {
+        p
+    } else if n < 0 {
+        _chunk(-n, f, p)
+    } else {
+        chunk_cycles(n, (0..n).rev(), f, p)
+    }
+}
+
+/// Like `chunk` but works backwards through the parts, starting with the last. Tidal's `chunk'`.
+pub fn chunk_back<A: 'static>(n: Pattern<isize>, f: impl Fn(Pattern<A>) -> Pattern<A> + Clone + Send + Sync + 'static, p: Pattern<A>) -> Pattern<A> {
+    t_param(move |n, p| _chunk_back(n, f.clone(), p), n, p)
+}
+
+/// Applies `f` to the cycles whose number passes `test`.
+pub fn when<A: 'static>(test: impl Fn(isize) -> bool + Send + Sync + 'static, f: impl Fn(Pattern<A>) -> Pattern<A>, p: Pattern<A>) -> Pattern<A> {
+    let fp = f(p.clone());
+    split_queries(pattern!(move |arc: Arc| {
+        if test(sam(arc.start).to_integer()) {
+            fp(arc)
+        } else {
+            p(arc)
+        }
+    }))
+}
+
+fn _every<A: 'static>(n: isize, f: impl Fn(Pattern<A>) -> Pattern<A>, p: Pattern<A>) -> Pattern<A> {
+    if n == 0 {
+        p
+    } else {
+        when(move |c| c.rem_euclid(n) == 0, f, p)
+    }
+}
+
+/// Applies `f` every `n` cycles, starting with the first.
+pub fn every<A: 'static>(n: Pattern<isize>, f: impl Fn(Pattern<A>) -> Pattern<A> + Clone + Send + Sync + 'static, p: Pattern<A>) -> Pattern<A> {
+    t_param(move |n, p| _every(n, f.clone(), p), n, p)
+}
+
+fn _every_offset<A: 'static>(n: isize, o: isize, f: impl Fn(Pattern<A>) -> Pattern<A>, p: Pattern<A>) -> Pattern<A> {
+    if n == 0 {
+        p
+    } else {
+        when(move |c| c.rem_euclid(n) == o.rem_euclid(n), f, p)
+    }
+}
+
+/// Applies `f` every `n` cycles, starting with cycle `o`. Tidal's `every'`.
+pub fn every_offset<A: 'static>(n: Pattern<isize>, o: Pattern<isize>, f: impl Fn(Pattern<A>) -> Pattern<A> + Clone + Send + Sync + 'static, p: Pattern<A>) -> Pattern<A> {
+    t_param2(move |n, o, p| _every_offset(n, o, f.clone(), p), n, o, p)
+}
+
+/// Applies `f` every `n` cycles, starting with the first. The same as `every`.
+pub fn first_of<A: 'static>(n: Pattern<isize>, f: impl Fn(Pattern<A>) -> Pattern<A> + Clone + Send + Sync + 'static, p: Pattern<A>) -> Pattern<A> {
+    every(n, f, p)
+}
+
+/// Applies `f` every `n` cycles, ending with cycle `n - 1`.
+pub fn last_of<A: 'static>(n: Pattern<isize>, f: impl Fn(Pattern<A>) -> Pattern<A> + Clone + Send + Sync + 'static, p: Pattern<A>) -> Pattern<A> {
+    t_param(move |n, p| _every_offset(n, n - 1, f.clone(), p), n, p)
+}
+
+fn _whenmod<A: 'static>(a: isize, b: isize, f: impl Fn(Pattern<A>) -> Pattern<A>, p: Pattern<A>) -> Pattern<A> {
+    if a == 0 {
+        p
+    } else {
+        when(move |c| c.rem_euclid(a) >= b, f, p)
+    }
+}
+
+/// Applies `f` to cycles whose number modulo `a` is at least `b`.
+pub fn whenmod<A: 'static>(a: Pattern<isize>, b: Pattern<isize>, f: impl Fn(Pattern<A>) -> Pattern<A> + Clone + Send + Sync + 'static, p: Pattern<A>) -> Pattern<A> {
+    t_param2(move |a, b, p| _whenmod(a, b, f.clone(), p), a, b, p)
+}
+
+fn _some_cycles_by<A: 'static>(x: f32, f: impl Fn(Pattern<A>) -> Pattern<A>, p: Pattern<A>) -> Pattern<A> {
+    when(move |c| time_rand(c.into()) < x as f64, f, p)
+}
+
+/// Applies `f` to whole cycles at random, with probability `x`.
+pub fn some_cycles_by<A: 'static>(x: Pattern<f32>, f: impl Fn(Pattern<A>) -> Pattern<A> + Clone + Send + Sync + 'static, p: Pattern<A>) -> Pattern<A> {
+    t_param(move |x, p| _some_cycles_by(x, f.clone(), p), x, p)
+}
+
+/// Applies `f` to half of all cycles at random.
+pub fn some_cycles<A: 'static>(f: impl Fn(Pattern<A>) -> Pattern<A> + Clone + Send + Sync + 'static, p: Pattern<A>) -> Pattern<A> {
+    some_cycles_by(unit(0.5), f, p)
+}
+
+/// Keeps only the parts of the pattern where `b` is true.
+pub fn mask<A: 'static + Clone>(b: Pattern<bool>, p: Pattern<A>) -> Pattern<A> {
+    apply_from_left(|a, _| a, p, filter_values(|b| *b, b))
+}
+
+/// Switches between two patterns, playing `a` where `b` is true and `c` where it's false.
+pub fn sew<A: 'static + Clone>(b: Pattern<bool>, a: Pattern<A>, c: Pattern<A>) -> Pattern<A> {
+    let inverse = b.clone();
+    let inverse = pattern!(move |arc| {
+        inverse(arc).into_iter().map(|e: Event<bool>| Event {
+            whole: e.whole,
+            part: e.part,
+            value: !e.value,
+        }).collect()
+    });
+    overlay(mask(b, a), mask(inverse, c))
+}
+
+/// Applies `f` where `b` is true. Tidal's `while`.
+pub fn while_<A: 'static + Clone>(b: Pattern<bool>, f: impl Fn(Pattern<A>) -> Pattern<A>, p: Pattern<A>) -> Pattern<A> {
+    sew(b, f(p.clone()), p)
 }
 
 fn split_queries<A: 'static>(p: Pattern<A>) -> Pattern<A> {
@@ -494,25 +1016,23 @@ fn split_queries<A: 'static>(p: Pattern<A>) -> Pattern<A> {
 
 pub fn rev<A: 'static>(p: Pattern<A>) -> Pattern<A> {
     fn make_whole_relative<A>(e: Event<A>) -> Event<A> {
-        if e.whole.is_none() {
-            e
-        } else {
-            Event {
-                whole: Some(Arc { start: e.part.start-e.whole.unwrap().start, stop: e.whole.unwrap().stop-e.part.stop }),
+        match e.whole {
+            None => e,
+            Some(whole) => Event {
+                whole: Some(Arc { start: e.part.start-whole.start, stop: whole.stop-e.part.stop }),
                 part: e.part,
                 value: e.value,
-            }
+            },
         }
     }
     fn make_whole_absolute<A>(e: Event<A>) -> Event<A> {
-        if e.whole.is_none() {
-            e
-        } else {
-            Event {
-                whole: Some(Arc { start: e.part.start-e.whole.unwrap().stop, stop: e.part.stop+e.whole.unwrap().start }),
+        match e.whole {
+            None => e,
+            Some(whole) => Event {
+                whole: Some(Arc { start: e.part.start-whole.stop, stop: e.part.stop+whole.start }),
                 part: e.part,
                 value: e.value
-            }
+            },
         }
     }
     fn mid_cycle(a: Arc) -> Time {
@@ -543,3 +1063,673 @@ pub fn rev<A: 'static>(p: Pattern<A>) -> Pattern<A> {
 pub fn id<A: 'static>(p: Pattern<A>) -> Pattern<A> {
     p
 }
+
+/// Methods for chaining transformations, Tidal style. Each is the same as the
+/// free function of the same name.
+impl<A: 'static> Pattern<A> {
+    pub fn fast(self, r: Pattern<Time>) -> Self {
+        fast(r, self)
+    }
+
+    pub fn slow(self, r: Pattern<Time>) -> Self {
+        slow(r, self)
+    }
+
+    pub fn rev(self) -> Self {
+        rev(self)
+    }
+
+    pub fn rot_l(self, t: Time) -> Self {
+        rot_l(t, self)
+    }
+
+    pub fn rot_r(self, t: Time) -> Self {
+        rot_r(t, self)
+    }
+
+    pub fn every(self, n: Pattern<isize>, f: impl Fn(Pattern<A>) -> Pattern<A> + Clone + Send + Sync + 'static) -> Self {
+        every(n, f, self)
+    }
+
+    pub fn every_offset(self, n: Pattern<isize>, o: Pattern<isize>, f: impl Fn(Pattern<A>) -> Pattern<A> + Clone + Send + Sync + 'static) -> Self {
+        every_offset(n, o, f, self)
+    }
+
+    pub fn whenmod(self, a: Pattern<isize>, b: Pattern<isize>, f: impl Fn(Pattern<A>) -> Pattern<A> + Clone + Send + Sync + 'static) -> Self {
+        whenmod(a, b, f, self)
+    }
+
+    pub fn first_of(self, n: Pattern<isize>, f: impl Fn(Pattern<A>) -> Pattern<A> + Clone + Send + Sync + 'static) -> Self {
+        first_of(n, f, self)
+    }
+
+    pub fn last_of(self, n: Pattern<isize>, f: impl Fn(Pattern<A>) -> Pattern<A> + Clone + Send + Sync + 'static) -> Self {
+        last_of(n, f, self)
+    }
+
+    pub fn some_cycles_by(self, x: Pattern<f32>, f: impl Fn(Pattern<A>) -> Pattern<A> + Clone + Send + Sync + 'static) -> Self {
+        some_cycles_by(x, f, self)
+    }
+
+    pub fn within(self, a: Arc, f: impl Fn(Pattern<A>) -> Pattern<A> + Send + Sync + 'static) -> Self {
+        within(a, f, self)
+    }
+
+    pub fn chunk(self, n: Pattern<isize>, f: impl Fn(Pattern<A>) -> Pattern<A> + Clone + Send + Sync + 'static) -> Self {
+        chunk(n, f, self)
+    }
+
+    pub fn chunk_back(self, n: Pattern<isize>, f: impl Fn(Pattern<A>) -> Pattern<A> + Clone + Send + Sync + 'static) -> Self {
+        chunk_back(n, f, self)
+    }
+
+    pub fn off(self, t: Pattern<Time>, f: impl Fn(&Pattern<A>) -> Pattern<A> + Send + Sync + 'static + Clone) -> Self {
+        off(t, f, self)
+    }
+
+    pub fn superimpose(self, f: impl Fn(&Pattern<A>) -> Pattern<A>) -> Self {
+        superimpose(f, self)
+    }
+
+    pub fn overlay(self, other: Self) -> Self {
+        overlay(self, other)
+    }
+
+    pub fn degrade_by(self, prob: Pattern<f32>) -> Self {
+        degrade_by(prob, self)
+    }
+
+    pub fn undegrade_by(self, prob: Pattern<f32>) -> Self {
+        undegrade_by(prob, self)
+    }
+
+    pub fn sometimes_by(self, x: Pattern<f32>, f: impl Fn(Pattern<A>) -> Pattern<A> + Send + Sync) -> Self {
+        sometimes_by(x, f, self)
+    }
+
+    pub fn euclid(self, k: Pattern<isize>, n: Pattern<isize>) -> Self {
+        euclid(k, n, self)
+    }
+
+    pub fn euclid_off(self, k: Pattern<isize>, n: Pattern<isize>, r: Pattern<isize>) -> Self {
+        euclid_off(k, n, r, self)
+    }
+
+    pub fn segment(self, n: Pattern<Time>) -> Self where A: Clone {
+        segment(n, self)
+    }
+
+    pub fn filter_values(self, f: impl Fn(&A) -> bool + Send + Sync + 'static) -> Self {
+        filter_values(f, self)
+    }
+}
+
+impl Pattern<f32> {
+    pub fn range(self, from: Pattern<f32>, to: Pattern<f32>) -> Self {
+        range(from, to, self)
+    }
+}
+
+impl<T: Operand> Pattern<T> {
+    /// Tidal's `#`. See `merge`.
+    pub fn merge(self, other: Self) -> Self {
+        merge(self, other)
+    }
+}
+
+impl Pattern<ControlMap> {
+    /// Plays the pattern on the left and `f` applied to it on the right.
+    pub fn jux(self, f: impl Fn(Pattern<ControlMap>) -> Pattern<ControlMap>) -> Self {
+        jux_by(unit(1.0), f, self)
+    }
+
+    pub fn hurry(self, r: Pattern<Time>) -> Self {
+        hurry(r, self)
+    }
+
+    pub fn jux_by(self, n: Pattern<f32>, f: impl Fn(Pattern<ControlMap>) -> Pattern<ControlMap>) -> Self {
+        jux_by(n, f, self)
+    }
+}
+
+/// Arithmetic on patterns takes structure from both sides, like Tidal's `|+|`.
+macro_rules! pattern_op {
+    ($trait:ident, $method:ident, $f:ident) => {
+        impl<T: Operand> std::ops::$trait for Pattern<T> {
+            type Output = Self;
+
+            fn $method(self, other: Self) -> Self {
+                $f(Structure::Both, self, other)
+            }
+        }
+    };
+}
+
+pattern_op!(Add, add, add);
+pattern_op!(Sub, sub, sub);
+pattern_op!(Mul, mul, mul);
+pattern_op!(Div, div, div);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arc(start: impl Into<Rational>, stop: impl Into<Rational>) -> Arc {
+        Arc { start: start.into(), stop: stop.into() }
+    }
+
+    fn query<A: Clone>(p: &Pattern<A>, a: Arc) -> Vec<(Option<Arc>, Arc, A)> {
+        p(a).into_iter().map(|e| (e.whole, e.part, e.value)).collect()
+    }
+
+    #[test]
+    fn sect_is_the_intersection() {
+        assert_eq!(sect(arc(0, 2), arc(1, 3)), arc(1, 2));
+        assert_eq!(sect(arc(0, 3), arc(1, 2)), arc(1, 2));
+    }
+
+    #[test]
+    fn sub_arc_of_overlapping_arcs() {
+        assert_eq!(sub_arc(arc(0, 1), arc((1, 2), 2)), Some(arc((1, 2), 1)));
+        assert_eq!(sub_arc(arc((1, 4), (3, 4)), arc(0, 1)), Some(arc((1, 4), (3, 4))));
+    }
+
+    #[test]
+    fn sub_arc_of_disjoint_arcs() {
+        assert_eq!(sub_arc(arc(0, 1), arc(2, 3)), None);
+        assert_eq!(sub_arc(arc(2, 3), arc(0, 1)), None);
+    }
+
+    #[test]
+    fn sub_arc_of_touching_arcs() {
+        // Arcs are half open, so ones which only meet at an end don't overlap.
+        assert_eq!(sub_arc(arc(0, 1), arc(1, 2)), None);
+        assert_eq!(sub_arc(arc(1, 2), arc(0, 1)), None);
+    }
+
+    #[test]
+    fn sub_arc_with_zero_width_arcs() {
+        assert_eq!(sub_arc(arc(0, 1), arc((1, 2), (1, 2))), Some(arc((1, 2), (1, 2))));
+        assert_eq!(sub_arc(arc(0, 1), arc(0, 0)), Some(arc(0, 0)));
+        assert_eq!(sub_arc(arc(1, 1), arc(0, 1)), None);
+        assert_eq!(sub_arc(arc(0, 1), arc(1, 1)), None);
+    }
+
+    #[test]
+    fn inner_join_queries_inner_patterns_at_the_same_time() {
+        let inner = cat(vec![unit('a'), unit('b'), unit('x')]);
+        let p = inner_join(cat(vec![unit(inner), unit(unit('c'))]));
+        assert_eq!(query(&p, arc(0, 4)), vec![
+            (Some(arc(0, 1)), arc(0, 1), 'a'),
+            (Some(arc(1, 2)), arc(1, 2), 'c'),
+            (Some(arc(2, 3)), arc(2, 3), 'x'),
+            (Some(arc(3, 4)), arc(3, 4), 'c'),
+        ]);
+    }
+
+    #[test]
+    fn inner_join_of_silence() {
+        let p: Pattern<char> = inner_join(unit(silence()));
+        assert!(query(&p, arc(0, 2)).is_empty());
+    }
+
+    #[test]
+    fn unit_covers_queries_which_start_mid_cycle() {
+        assert_eq!(query(&unit('a'), arc((1, 2), (5, 2))), vec![
+            (Some(arc(0, 1)), arc((1, 2), 1), 'a'),
+            (Some(arc(1, 2)), arc(1, 2), 'a'),
+            (Some(arc(2, 3)), arc(2, (5, 2)), 'a'),
+        ]);
+        assert_eq!(query(&unit('a'), arc((1, 3), (2, 3))), vec![
+            (Some(arc(0, 1)), arc((1, 3), (2, 3)), 'a'),
+        ]);
+    }
+
+    #[test]
+    fn unit_has_one_onset_per_cycle_however_queries_are_split() {
+        // The player queries in small slices and only plays fragments which
+        // start their whole, so each cycle has to have exactly one of those.
+        let p = unit('a');
+        let mut onsets = vec![];
+        let mut start = Rational::from(0);
+        while start < Rational::from(3) {
+            let stop = start + Rational::new(3, 10);
+            onsets.extend(p(arc(start, stop)).into_iter().filter(|e| e.whole.is_some_and(|w| w.start == e.part.start)).map(|e| e.part.start));
+            start = stop;
+        }
+        assert_eq!(onsets, vec![Rational::from(0), Rational::from(1), Rational::from(2)]);
+    }
+
+    fn steps(s: &str) -> Vec<bool> {
+        s.chars().map(|c| c == 'x').collect()
+    }
+
+    #[test]
+    fn bjorklund_matches_toussaint() {
+        // From Toussaint's "The Euclidean Algorithm Generates Traditional Musical Rhythms".
+        let table = [
+            (2, 5, "x.x.."),
+            (3, 5, "x.x.x"),
+            (3, 7, "x.x.x.."),
+            (3, 8, "x..x..x."),
+            (4, 7, "x.x.x.x"),
+            (4, 9, "x.x.x.x.."),
+            (4, 11, "x..x..x..x."),
+            (5, 7, "x.xx.xx"),
+            (5, 8, "x.xx.xx."),
+            (5, 9, "x.x.x.x.x"),
+            (5, 11, "x.x.x.x.x.."),
+            (5, 12, "x..x.x..x.x."),
+            (5, 16, "x..x..x..x..x..."),
+            (7, 12, "x.xx.x.xx.x."),
+            (7, 16, "x..x.x.x..x.x.x."),
+            (9, 16, "x.xx.x.x.xx.x.x."),
+            (11, 24, "x..x.x.x.x.x..x.x.x.x.x."),
+            (13, 24, "x.xx.x.x.x.x.xx.x.x.x.x."),
+        ];
+        for (k, n, expected) in table.iter() {
+            assert_eq!(bjorklund(*k, *n), steps(expected), "({}, {})", k, n);
+        }
+    }
+
+    #[test]
+    fn bjorklund_follows_tidal_where_it_differs_from_toussaint() {
+        // The output matches Tidal rather than the paper. For these Tidal's
+        // algorithm stops with the rests at the end, giving a rotation of
+        // Toussaint's rhythm.
+        let table = [
+            (3, 4, "xxx.", "x.xx"),
+            (5, 6, "xxxxx.", "x.xxxx"),
+            (7, 8, "xxxxxxx.", "x.xxxxxx"),
+        ];
+        for (k, n, tidal, toussaint) in table.iter() {
+            let result = bjorklund(*k, *n);
+            assert_eq!(result, steps(tidal), "({}, {})", k, n);
+            let mut rotated = result.clone();
+            rotated.rotate_right(2);
+            assert_eq!(rotated, steps(toussaint), "({}, {})", k, n);
+        }
+    }
+
+    #[test]
+    fn bjorklund_edge_cases() {
+        assert_eq!(bjorklund(0, 4), steps("...."));
+        assert_eq!(bjorklund(4, 4), steps("xxxx"));
+        assert_eq!(bjorklund(1, 4), steps("x..."));
+    }
+
+    /// Tidal's `appLeft` worked out another way. The right pattern is queried
+    /// once over everything the left events cover and its discrete events are
+    /// paired up by intersecting parts. Continuous values are sampled at the
+    /// middle of each left event's whole, which is what querying over it gives.
+    fn apply_from_left_reference<A: Clone, B: Clone>(lhs: &Pattern<A>, rhs: &Pattern<B>, a: Arc) -> Vec<Event<(A, B)>> {
+        let lefts = lhs(a);
+        let wholes: Vec<Arc> = lefts.iter().map(|l| l.whole_or_part()).collect();
+        let span = match (wholes.iter().map(|w| w.start).min(), wholes.iter().map(|w| w.stop).max()) {
+            (Some(start), Some(stop)) => Arc { start, stop },
+            _ => return vec![],
+        };
+        let rights = rhs(span);
+        let mut results = vec![];
+        for (l, whole) in lefts.iter().zip(wholes) {
+            for r in rights.iter().filter(|r| r.whole.is_some()) {
+                let start = l.part.start.max(r.part.start);
+                let stop = l.part.stop.min(r.part.stop);
+                // A zero width overlap only counts if it isn't just the end of a
+                // non-zero width part.
+                let at_end = |p: Arc| start == p.stop && p.start < p.stop;
+                if start > stop || (start == stop && (at_end(l.part) || at_end(r.part))) {
+                    continue;
+                }
+                results.push(Event {
+                    whole: l.whole,
+                    part: Arc { start, stop },
+                    value: (l.value.clone(), r.value.clone()),
+                });
+            }
+            let middle = (whole.start + whole.stop) / 2;
+            for r in rhs(Arc { start: middle, stop: middle }).into_iter().filter(|r| r.whole.is_none()) {
+                results.push(Event {
+                    whole: l.whole,
+                    part: l.part,
+                    value: (l.value.clone(), r.value),
+                });
+            }
+        }
+        results
+    }
+
+    /// Deterministic pseudo-random numbers from Tidal's xorshift.
+    struct Random(i64);
+
+    impl Random {
+        fn below(&mut self, n: i64) -> i64 {
+            self.0 = xorwise(self.0);
+            self.0.rem_euclid(n)
+        }
+
+        fn rational(&mut self, max: i64, denom: i64) -> Rational {
+            Rational::new(self.below(max * denom) as isize, denom as isize)
+        }
+    }
+
+    /// A random mix of discrete sequences at odd speeds and offsets, with
+    /// continuous events on top some of the time.
+    fn random_pattern(random: &mut Random) -> Pattern<f32> {
+        let steps = (0..random.below(5) + 1).map(|i| {
+            if random.below(5) == 0 { silence() } else { unit(i as f32) }
+        }).collect();
+        let speed = Rational::new(random.below(4) as isize + 1, random.below(3) as isize + 1);
+        let discrete = rot_r(random.rational(2, 8), _fast(speed, fastcat(steps)));
+        match random.below(3) {
+            0 => discrete,
+            1 => stack(vec![discrete, rot_r(random.rational(1, 8), _fast(speed, saw()))]),
+            _ => _fast(speed, saw()),
+        }
+    }
+
+    fn sorted<A: PartialOrd>(mut events: Vec<Event<A>>) -> Vec<(Option<Arc>, Arc, A)> {
+        events.sort_by(|a, b| {
+            let key = |e: &Event<A>| (e.part.start, e.part.stop, e.whole.map(|w| (w.start, w.stop)));
+            key(a).cmp(&key(b)).then(a.value.partial_cmp(&b.value).unwrap())
+        });
+        events.into_iter().map(|e| (e.whole, e.part, e.value)).collect()
+    }
+
+    #[test]
+    fn apply_from_left_matches_reference() {
+        let mut random = Random(1);
+        for _ in 0..500 {
+            let lhs = random_pattern(&mut random);
+            let rhs = random_pattern(&mut random);
+            let start = random.rational(4, 8) - 1;
+            let a = Arc { start, stop: start + random.rational(3, 8) };
+            let expected = sorted(apply_from_left_reference(&lhs, &rhs, a));
+            let result = sorted(apply_from_left(|l, r| (l, r), lhs, rhs)(a));
+            assert_eq!(result, expected, "querying {:?}", a);
+        }
+    }
+
+    #[test]
+    fn apply_from_left_with_continuous_events() {
+        // Continuous events on the left keep having no whole, and ones on the
+        // right are sampled over each left event's whole.
+        let p = apply_from_left(|l, r| (l, r), saw(), unit(1.0));
+        assert_eq!(query(&p, arc(0, (1, 2))), vec![(None, arc(0, (1, 2)), (0.25, 1.0))]);
+        let p = apply_from_left(|l, r| (l, r), fastcat(vec![unit(0.0), unit(1.0)]), saw());
+        assert_eq!(query(&p, arc(0, 1)), vec![
+            (Some(arc(0, (1, 2))), arc(0, (1, 2)), (0.0, 0.25)),
+            (Some(arc((1, 2), 1)), arc((1, 2), 1), (1.0, 0.75)),
+        ]);
+    }
+
+    /// The sample name and `speed` of the events in a cycle, by eighths of
+    /// the cycle, checking that none are fragments.
+    fn sounds_in_cycle(p: &Pattern<ControlMap>, cycle: isize) -> Vec<(isize, isize, String, Option<f32>)> {
+        let mut events = p(arc(cycle, cycle + 1));
+        events.sort_by_key(|e| e.part.start);
+        events.into_iter().map(|e| {
+            assert_eq!(e.whole, Some(e.part));
+            let eighths = |t: Time| ((t - cycle) * 8).to_integer();
+            let s = match &e.value.0["s"] {
+                Value::String(s) => s.clone(),
+                v => panic!("unexpected s {:?}", v),
+            };
+            let speed = e.value.0.get("speed").and_then(Value::as_f64).map(|v| v as f32);
+            (eighths(e.part.start), eighths(e.part.stop), s, speed)
+        }).collect()
+    }
+
+    fn expected_sounds(events: &[(isize, isize, &str, Option<f32>)]) -> Vec<(isize, isize, String, Option<f32>)> {
+        events.iter().map(|(start, stop, s, speed)| (*start, *stop, s.to_string(), *speed)).collect()
+    }
+
+    #[test]
+    fn chunk_hurries_one_quarter_per_cycle() {
+        let p = chunk(unit(4), |p| hurry(unit(2.into()), p), sound(mini_notation::parse_pattern("a b c d")));
+        let cycles = [
+            vec![(0, 1, "a", Some(2.0)), (1, 2, "b", Some(2.0)), (2, 4, "b", None), (4, 6, "c", None), (6, 8, "d", None)],
+            vec![(0, 2, "a", None), (2, 3, "c", Some(2.0)), (3, 4, "d", Some(2.0)), (4, 6, "c", None), (6, 8, "d", None)],
+            vec![(0, 2, "a", None), (2, 4, "b", None), (4, 5, "a", Some(2.0)), (5, 6, "b", Some(2.0)), (6, 8, "d", None)],
+            vec![(0, 2, "a", None), (2, 4, "b", None), (4, 6, "c", None), (6, 7, "c", Some(2.0)), (7, 8, "d", Some(2.0))],
+        ];
+        for (cycle, expected) in cycles.iter().enumerate() {
+            assert_eq!(sounds_in_cycle(&p, cycle as isize), expected_sounds(expected), "cycle {}", cycle);
+            assert_eq!(sounds_in_cycle(&p, cycle as isize + 4), expected_sounds(expected), "cycle {}", cycle + 4);
+        }
+    }
+
+    #[test]
+    fn chunk_back_starts_with_the_last_quarter() {
+        let p = chunk_back(unit(4), |p| hurry(unit(2.into()), p), sound(mini_notation::parse_pattern("a b c d")));
+        let cycles = [
+            vec![(0, 2, "a", None), (2, 4, "b", None), (4, 6, "c", None), (6, 7, "c", Some(2.0)), (7, 8, "d", Some(2.0))],
+            vec![(0, 2, "a", None), (2, 4, "b", None), (4, 5, "a", Some(2.0)), (5, 6, "b", Some(2.0)), (6, 8, "d", None)],
+            vec![(0, 2, "a", None), (2, 3, "c", Some(2.0)), (3, 4, "d", Some(2.0)), (4, 6, "c", None), (6, 8, "d", None)],
+            vec![(0, 1, "a", Some(2.0)), (1, 2, "b", Some(2.0)), (2, 4, "b", None), (4, 6, "c", None), (6, 8, "d", None)],
+        ];
+        for (cycle, expected) in cycles.iter().enumerate() {
+            assert_eq!(sounds_in_cycle(&p, cycle as isize), expected_sounds(expected), "cycle {}", cycle);
+        }
+    }
+
+    #[test]
+    fn chunk_with_a_patterned_number_of_parts() {
+        // Halves in even cycles and quarters in odd ones, so cycle 1 hurries
+        // its second quarter, cycle 2 its first half and cycle 3 its last quarter.
+        let p = chunk(mini_notation::parse_pattern("<2 4>"), |p| hurry(unit(2.into()), p), sound(mini_notation::parse_pattern("a b c d")));
+        let cycles = [
+            vec![(0, 1, "a", Some(2.0)), (1, 2, "b", Some(2.0)), (2, 3, "c", Some(2.0)), (3, 4, "d", Some(2.0)), (4, 6, "c", None), (6, 8, "d", None)],
+            vec![(0, 2, "a", None), (2, 3, "c", Some(2.0)), (3, 4, "d", Some(2.0)), (4, 6, "c", None), (6, 8, "d", None)],
+            vec![(0, 1, "a", Some(2.0)), (1, 2, "b", Some(2.0)), (2, 3, "c", Some(2.0)), (3, 4, "d", Some(2.0)), (4, 6, "c", None), (6, 8, "d", None)],
+            vec![(0, 2, "a", None), (2, 4, "b", None), (4, 6, "c", None), (6, 7, "c", Some(2.0)), (7, 8, "d", Some(2.0))],
+        ];
+        for (cycle, expected) in cycles.iter().enumerate() {
+            assert_eq!(sounds_in_cycle(&p, cycle as isize), expected_sounds(expected), "cycle {}", cycle);
+        }
+    }
+
+    fn sample<A: Clone>(p: &Pattern<A>, t: Rational) -> A {
+        p(Arc { start: t, stop: t }).remove(0).value
+    }
+
+    #[test]
+    fn rand_matches_tidal() {
+        // The values Tidal's own tests expect.
+        assert!((sample(&rand(), Rational::new(1, 4)) - 0.6295689).abs() < 1e-6);
+        assert!((sample(&rand(), Rational::new(3, 4)) - 0.2005262).abs() < 1e-6);
+        assert_eq!(sample(&irand(unit(10)), Rational::new(1, 4)), 6);
+        assert_eq!(sample(&irand(unit(10)), Rational::new(3, 4)), 2);
+    }
+
+    #[test]
+    fn degrade_by_keeps_the_same_events() {
+        let values = |p: Pattern<isize>| -> Vec<isize> {
+            let mut events = p(arc(0, 1));
+            events.sort_by_key(|e| e.part.start);
+            events.into_iter().map(|e| e.value).collect()
+        };
+        let eighths = || fastcat((0..8).map(unit).collect());
+        assert_eq!(values(degrade_by(unit(0.5), eighths())), vec![1, 2, 3, 4, 6, 7]);
+        assert_eq!(values(sometimes_by(unit(0.5), |p| p + unit(10), eighths())), vec![10, 1, 2, 3, 4, 15, 6, 7]);
+    }
+
+    /// The cycles from 0 to 7 in which `f` replaced the pattern with `x`.
+    fn applied(p: Pattern<String>) -> Vec<isize> {
+        (0..8).filter(|c| p(arc(*c, c + 1)).iter().any(|e| e.value == "x")).collect()
+    }
+
+    fn x(_: Pattern<String>) -> Pattern<String> {
+        unit("x".to_string())
+    }
+
+    fn strings(s: &str) -> Pattern<String> {
+        mini_notation::parse_pattern(s)
+    }
+
+    #[test]
+    fn every_n_cycles() {
+        assert_eq!(applied(every(unit(3), x, strings("a"))), vec![0, 3, 6]);
+        assert_eq!(applied(first_of(unit(3), x, strings("a"))), vec![0, 3, 6]);
+        assert_eq!(applied(last_of(unit(3), x, strings("a"))), vec![2, 5]);
+        assert_eq!(applied(every_offset(unit(3), unit(1), x, strings("a"))), vec![1, 4, 7]);
+        assert_eq!(applied(every(unit(0), x, strings("a"))), vec![]);
+        // Each cycle uses the number in force at its start.
+        assert_eq!(applied(every(mini_notation::parse_pattern("<2 3>"), x, strings("a"))), vec![0, 2, 3, 4, 6]);
+    }
+
+    #[test]
+    fn whenmod_cycles() {
+        assert_eq!(applied(whenmod(unit(4), unit(2), x, strings("a"))), vec![2, 3, 6, 7]);
+    }
+
+    #[test]
+    fn when_tests_the_cycle_number() {
+        assert_eq!(applied(when(|c| c % 2 == 1, x, strings("a"))), vec![1, 3, 5, 7]);
+    }
+
+    #[test]
+    fn some_cycles_by_picks_the_same_cycles() {
+        assert_eq!(applied(some_cycles_by(unit(0.5), x, strings("a"))), vec![0, 2, 4, 5, 7]);
+        assert_eq!(applied(some_cycles_by(unit(0.0), x, strings("a"))), vec![]);
+        assert_eq!(applied(some_cycles_by(unit(1.0), x, strings("a"))), (0..8).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn mask_keeps_fragments_where_true() {
+        assert_eq!(query(&mask(mini_notation::parse_pattern("t f t t"), strings("a b")), arc(0, 1)), vec![
+            (Some(arc(0, (1, 2))), arc(0, (1, 4)), "a".to_string()),
+            (Some(arc((1, 2), 1)), arc((1, 2), (3, 4)), "b".to_string()),
+            (Some(arc((1, 2), 1)), arc((3, 4), 1), "b".to_string()),
+        ]);
+        assert_eq!(query(&mask(mini_notation::parse_pattern("0 1"), strings("a")), arc(0, 1)), vec![
+            (Some(arc(0, 1)), arc((1, 2), 1), "a".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn sew_switches_halfway() {
+        assert_eq!(query(&sew(mini_notation::parse_pattern("t f"), strings("a"), strings("b c")), arc(0, 1)), vec![
+            (Some(arc(0, 1)), arc(0, (1, 2)), "a".to_string()),
+            (Some(arc((1, 2), 1)), arc((1, 2), 1), "c".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn while_applies_where_true() {
+        assert_eq!(query(&while_(mini_notation::parse_pattern("1 0"), |p| _fast(2.into(), p), strings("a b")), arc(0, 1)), vec![
+            (Some(arc(0, (1, 4))), arc(0, (1, 4)), "a".to_string()),
+            (Some(arc((1, 4), (1, 2))), arc((1, 4), (1, 2)), "b".to_string()),
+            (Some(arc((1, 2), 1)), arc((1, 2), 1), "b".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn fast_by_zero_is_silence() {
+        assert_eq!(query(&fast(unit(0.into()), strings("a b")), arc(0, 4)), vec![]);
+        assert_eq!(query(&slow(unit(0.into()), strings("a b")), arc(0, 4)), vec![]);
+    }
+
+    #[test]
+    fn fast_by_a_negative_number_reverses() {
+        let mut events = query(&fast(unit((-2).into()), strings("a b c")), arc(0, (1, 2)));
+        events.sort_by_key(|(_, part, _)| part.start);
+        assert_eq!(events, vec![
+            (Some(arc(0, (1, 6))), arc(0, (1, 6)), "c".to_string()),
+            (Some(arc((1, 6), (1, 3))), arc((1, 6), (1, 3)), "b".to_string()),
+            (Some(arc((1, 3), (1, 2))), arc((1, 3), (1, 2)), "a".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn slow_keeps_exact_boundaries() {
+        let events = query(&slow(unit(3.into()), strings("a b c d")), arc(0, 1));
+        assert_eq!(events, vec![
+            (Some(arc(0, (3, 4))), arc(0, (3, 4)), "a".to_string()),
+            (Some(arc((3, 4), (3, 2))), arc((3, 4), 1), "b".to_string()),
+        ]);
+        let events = query(&_slow(Rational::new(3, 1), fastcat(vec![unit('a'), unit('b'), unit('c')])), arc(0, 1));
+        assert_eq!(events, vec![(Some(arc(0, 1)), arc(0, 1), 'a')]);
+        let events = query(&_fast(Rational::new(1, 3), _fast(Rational::new(3, 1), unit('a'))), arc((1, 3), (2, 3)));
+        assert_eq!(events, vec![(Some(arc(0, 1)), arc((1, 3), (2, 3)), 'a')]);
+    }
+
+    #[test]
+    fn patterned_fast() {
+        let p = fast(mini_notation::parse_pattern("<1 2>"), strings("a b"));
+        assert_eq!(query(&p, arc(1, 2)).len(), 4);
+        assert_eq!(query(&p, arc(2, 3)).len(), 2);
+    }
+
+    #[test]
+    fn hurry_multiplies_speed() {
+        let speeds = |p: Pattern<ControlMap>| -> Vec<(Rational, Option<Value>)> {
+            let mut events = p(arc(0, 1));
+            events.sort_by_key(|e| e.part.start);
+            events.into_iter().map(|e| (e.part.start, e.value.0.get("speed").cloned())).collect()
+        };
+        assert_eq!(speeds(hurry(unit(2.into()), sound(strings("a b")))), vec![
+            (Rational::from(0), Some(Value::Float(2.0))),
+            (Rational::new(1, 4), Some(Value::Float(2.0))),
+            (Rational::new(1, 2), Some(Value::Float(2.0))),
+            (Rational::new(3, 4), Some(Value::Float(2.0))),
+        ]);
+        let p = crate::operators::merge(sound(strings("a")), speed(mini_notation::parse_pattern("1.5")));
+        assert_eq!(speeds(hurry(unit(Rational::new(1, 2)), p)), vec![(Rational::from(0), Some(Value::Float(0.75)))]);
+    }
+
+    fn assert_close(actual: &[f32], expected: &[f32]) {
+        assert_eq!(actual.len(), expected.len(), "{:?} vs {:?}", actual, expected);
+        assert!(actual.iter().zip(expected).all(|(a, e)| (a - e).abs() < 1e-4), "{:?} vs {:?}", actual, expected);
+    }
+
+    /// The signal's value at each point.
+    fn samples(p: &Pattern<f32>, points: &[(isize, isize)]) -> Vec<f32> {
+        points.iter().map(|(n, d)| sample(p, Rational::new(*n, *d))).collect()
+    }
+
+    const QUARTERS: [(isize, isize); 4] = [(0, 1), (1, 4), (1, 2), (3, 4)];
+
+    #[test]
+    fn signals_at_fixed_points() {
+        assert_close(&samples(&sine(), &QUARTERS), &[0.5, 1.0, 0.5, 0.0]);
+        assert_close(&samples(&cosine(), &QUARTERS), &[0.0, 0.5, 1.0, 0.5]);
+        assert_close(&samples(&saw(), &QUARTERS), &[0.0, 0.25, 0.5, 0.75]);
+        assert_close(&samples(&isaw(), &QUARTERS), &[1.0, 0.75, 0.5, 0.25]);
+        assert_close(&samples(&tri(), &QUARTERS), &[0.0, 0.5, 1.0, 0.5]);
+        assert_close(&samples(&square(), &QUARTERS), &[0.0, 0.0, 1.0, 1.0]);
+        assert_close(&samples(&env_l(), &[(-1, 1), (1, 2), (3, 1)]), &[0.0, 0.5, 1.0]);
+    }
+
+    #[test]
+    fn perlin_interpolates_between_random_values() {
+        let a = time_rand(0.into()) as f32;
+        let b = time_rand(1.into()) as f32;
+        assert_close(&samples(&perlin(), &[(0, 1), (1, 2), (1, 1)]), &[a, (a + b) / 2.0, b]);
+    }
+
+    #[test]
+    fn signals_have_no_whole_until_segmented() {
+        assert_eq!(query(&sine(), arc(0, 1)).into_iter().map(|(whole, part, _)| (whole, part)).collect::<Vec<_>>(), vec![(None, arc(0, 1))]);
+        let segmented = query(&segment(unit(4.into()), sine()), arc(0, 1));
+        assert_eq!(segmented.iter().map(|(whole, part, _)| (*whole, *part)).collect::<Vec<_>>(), vec![
+            (Some(arc(0, (1, 4))), arc(0, (1, 4))),
+            (Some(arc((1, 4), (1, 2))), arc((1, 4), (1, 2))),
+            (Some(arc((1, 2), (3, 4))), arc((1, 2), (3, 4))),
+            (Some(arc((3, 4), 1)), arc((3, 4), 1)),
+        ]);
+        // Like Tidal, each segment samples the signal over its whole, so at its middle.
+        let high = (1.0 + std::f32::consts::FRAC_1_SQRT_2) / 2.0;
+        assert_close(&segmented.into_iter().map(|(_, _, v)| v).collect::<Vec<_>>(), &[high, high, 1.0 - high, 1.0 - high]);
+        // Shifting it half a segment later samples at the start of each instead.
+        assert_close(&query(&segment(unit(4.into()), rot_r(Rational::new(1, 8), sine())), arc(0, 1)).into_iter().map(|(_, _, v)| v).collect::<Vec<_>>(), &[0.5, 1.0, 0.5, 0.0]);
+    }
+
+    #[test]
+    fn range_scales_signals() {
+        assert_close(&samples(&range(unit(1.0), unit(5.0), saw()), &QUARTERS), &[1.0, 2.0, 3.0, 4.0]);
+        assert_close(&samples(&range(unit(1.0), unit(-1.0), saw()), &[(1, 2)]), &[0.0]);
+        let p = irange(unit(0), unit(4), saw());
+        assert_eq!(QUARTERS.iter().chain(&[(99, 100)]).map(|(n, d)| sample(&p, Rational::new(*n, *d))).collect::<Vec<_>>(), vec![0, 1, 2, 3, 3]);
+    }
+}
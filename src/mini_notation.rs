@@ -1,64 +1,558 @@
-use num::{
-    FromPrimitive,
-    rational::Rational,
+use num::rational::Rational;
+use pest::{
+    Parser, Span,
+    iterators::Pair,
+    error::{Error, ErrorVariant, LineColLocation},
 };
-use pest::{Parser, iterators::Pair};
 
-use crate::{Pattern, Event, fast, cat, unit};
+use crate::{Pattern, Time, _fast, _slow, fastcat, timecat, stack, cycle_choose_using, euclid_off, _degrade_by_using, rand, rot_l, unit, silence};
 
 #[derive(Parser)]
 #[grammar = "mini_notation.pest"]
 struct MiniNotationParser;
 
-pub fn parse_pattern(input: &'static str) -> Pattern<String> {
-    let pattern = MiniNotationParser::parse(Rule::pattern, input).unwrap_or_else(|e| panic!("{}", e)).next().unwrap();
-    _parse_pattern(pattern)
+/// An error encountered while parsing mini-notation. Positions are 1-based
+/// `(line, column)` pairs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+    pub expected: Vec<String>,
+    pub message: String,
 }
 
-fn _parse_pattern(pair: Pair<'static, Rule>) -> Pattern<String> {
-    match pair.as_rule() {
-        Rule::fast_repeat => {
-            let sequence: Vec<_> = pair.into_inner().next().unwrap().into_inner().map(_parse_pattern).collect();
-            let n = sequence.len();
-            fast((n as isize).into(), cat(sequence))
-        },
-        Rule::sequence => {
-            let sequence: Vec<_> = pair.into_inner().map(_parse_pattern).collect();
-            fast((sequence.len() as isize).into(), cat(sequence))
+impl ParseError {
+    fn custom(span: Span, message: impl Into<String>) -> Self {
+        Error::new_from_span(ErrorVariant::CustomError { message: message.into() }, span).into()
+    }
+}
+
+impl From<Error<Rule>> for ParseError {
+    fn from(e: Error<Rule>) -> Self {
+        let (start, end) = match e.line_col {
+            LineColLocation::Pos(p) => (p, p),
+            LineColLocation::Span(s, e) => (s, e),
+        };
+        let expected = match &e.variant {
+            ErrorVariant::ParsingError { positives, .. } => positives.iter().map(|r| format!("{:?}", r)).collect(),
+            ErrorVariant::CustomError { .. } => vec![],
+        };
+        Self {
+            start,
+            end,
+            expected,
+            message: e.variant.message().into_owned(),
+        }
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.start.0, self.start.1, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// A type which can appear as an event value in mini-notation.
+pub trait Atom: Clone + Send + Sync + 'static {
+    /// Used in error messages when an atom can't be converted.
+    const KIND: &'static str;
+
+    fn from_number(s: &str) -> Option<Self>;
+    fn from_word(s: &str) -> Option<Self>;
+}
+
+impl Atom for String {
+    const KIND: &'static str = "string";
+
+    fn from_number(s: &str) -> Option<Self> {
+        Some(s.to_string())
+    }
+    fn from_word(s: &str) -> Option<Self> {
+        Some(s.to_string())
+    }
+}
+
+impl Atom for f32 {
+    const KIND: &'static str = "number";
+
+    fn from_number(s: &str) -> Option<Self> {
+        s.parse().ok()
+    }
+    fn from_word(_: &str) -> Option<Self> {
+        None
+    }
+}
+
+impl Atom for isize {
+    const KIND: &'static str = "integer";
+
+    fn from_number(s: &str) -> Option<Self> {
+        s.parse().ok()
+    }
+    fn from_word(_: &str) -> Option<Self> {
+        None
+    }
+}
+
+/// Booleans are written `t` or `f`, or as `1` or `0`.
+impl Atom for bool {
+    const KIND: &'static str = "boolean";
+
+    fn from_number(s: &str) -> Option<Self> {
+        match s {
+            "1" => Some(true),
+            "0" => Some(false),
+            _ => None,
+        }
+    }
+    fn from_word(s: &str) -> Option<Self> {
+        match s {
+            "t" | "true" => Some(true),
+            "f" | "false" => Some(false),
+            _ => None,
+        }
+    }
+}
+
+impl Atom for Rational {
+    const KIND: &'static str = "rational";
+
+    fn from_number(s: &str) -> Option<Self> {
+        parse_rational(s)
+    }
+    fn from_word(_: &str) -> Option<Self> {
+        None
+    }
+}
+
+/// Parses a decimal like `-0.25` exactly, without going through a float.
+fn parse_rational(s: &str) -> Option<Rational> {
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(s) => (true, s),
+        None => (false, s),
+    };
+    let (whole, fraction) = match s.find('.') {
+        Some(i) => (&s[..i], &s[i+1..]),
+        None => (s, ""),
+    };
+    let mut numer: isize = whole.parse().ok()?;
+    let mut denom: isize = 1;
+    for digit in fraction.chars() {
+        numer = numer.checked_mul(10)?.checked_add(digit.to_digit(10)? as isize)?;
+        denom = denom.checked_mul(10)?;
+    }
+    let r = Rational::new(numer, denom);
+    Some(if negative { -r } else { r })
+}
+
+pub fn parse_pattern<T: Atom>(input: &str) -> Pattern<T> {
+    try_parse_pattern(input).unwrap_or_else(|e| panic!("{}", e))
+}
+
+pub fn try_parse_pattern<T: Atom>(input: &str) -> Result<Pattern<T>, ParseError> {
+    let pattern = MiniNotationParser::parse(Rule::pattern, input)?.next().unwrap();
+    parse_layers(pattern.into_inner().next().unwrap(), &mut 0)
+}
+
+/// Numbers the random choices in the order they're parsed, like Tidal does,
+/// so that each `?` and `|` gets a different stream of random numbers.
+fn new_seed(seed: &mut isize) -> isize {
+    *seed += 1;
+    *seed - 1
+}
+
+/// Tidal's `rand` shifted by the seed.
+fn seeded_rand(seed: isize) -> Pattern<f32> {
+    rot_l(Rational::new(seed, 10000), rand())
+}
+
+fn parse_layers<T: Atom>(pair: Pair<Rule>, seed: &mut isize) -> Result<Pattern<T>, ParseError> {
+    let pair = pair.into_inner().next().unwrap();
+    Ok(match pair.as_rule() {
+        Rule::stack => {
+            let layers = pair.into_inner().map(|p| parse_sequence(p, seed).map(|(_, p)| p)).collect::<Result<Vec<_>, _>>()?;
+            stack(layers)
         },
-        Rule::cycle => {
-            let sequence: Vec<_> = pair.into_inner().next().unwrap().into_inner().map(_parse_pattern).collect();
-            cat(sequence)
+        Rule::choice => {
+            let choices = pair.into_inner().map(|p| parse_sequence(p, seed).map(|(_, p)| p)).collect::<Result<Vec<_>, _>>()?;
+            cycle_choose_using(seeded_rand(new_seed(seed)), choices)
         },
+        Rule::sequence => parse_sequence(pair, seed)?.1,
+        _ => unreachable!(),
+    })
+}
 
-        Rule::modified_event => {
-            let mut inner = pair.into_inner();
-            let event = _parse_pattern(inner.next().unwrap());
-            let operator = inner.next().unwrap();
-            let number:f32 = inner.next().unwrap().as_str().parse().unwrap();
-            assert!(inner.next().is_none());
+/// Stacks the layers so that each plays `steps` steps per cycle, defaulting to the
+/// number of steps in the first layer.
+fn parse_polymeter<T: Atom>(pair: Pair<Rule>, steps: Option<Time>, seed: &mut isize) -> Result<Pattern<T>, ParseError> {
+    let pair = pair.into_inner().next().unwrap();
+    let layers = match pair.as_rule() {
+        Rule::stack => pair.into_inner().map(|p| parse_sequence(p, seed)).collect::<Result<Vec<_>, _>>()?,
+        Rule::sequence => vec![parse_sequence(pair, seed)?],
+        Rule::choice => return Err(ParseError::custom(pair.as_span(), "random choice can't be used here")),
+        _ => unreachable!(),
+    };
+    let steps = steps.unwrap_or(layers[0].0);
+    Ok(stack(layers.into_iter().map(|(n, p)| _fast(steps / n, p)).collect()))
+}
 
-            match operator.as_str() {
-                "*" => {
-                    fast(Rational::from_f32(number).unwrap(), event)
-                },
-                "/" => {
-                    fast(Rational::from_f32(1.0/number).unwrap(), event)
+/// Returns the sequence along with the number of steps in it.
+fn parse_sequence<T: Atom>(pair: Pair<Rule>, seed: &mut isize) -> Result<(Time, Pattern<T>), ParseError> {
+    let mut groups = pair.into_inner().map(|p| parse_steps(p, seed)).collect::<Result<Vec<_>, _>>()?;
+    if groups.len() == 1 {
+        let steps = groups.pop().unwrap();
+        let total = steps.iter().fold(Time::from(0), |total, (w, _)| total + w);
+        Ok((total, timecat(steps)))
+    } else {
+        let n = groups.len() as isize;
+        Ok((n.into(), fastcat(groups.into_iter().map(timecat).collect())))
+    }
+}
+
+fn parse_steps<T: Atom>(pair: Pair<Rule>, seed: &mut isize) -> Result<Vec<(Time, Pattern<T>)>, ParseError> {
+    let mut steps: Vec<(Time, Pattern<T>)> = vec![];
+    for step in pair.into_inner() {
+        let step = step.into_inner().next().unwrap();
+        match step.as_rule() {
+            Rule::hold => {
+                let last = steps.last_mut().ok_or_else(|| ParseError::custom(step.as_span(), "nothing to extend"))?;
+                last.0 += 1;
+            },
+            Rule::repeat => {
+                let last = steps.last().cloned().ok_or_else(|| ParseError::custom(step.as_span(), "nothing to repeat"))?;
+                steps.push(last);
+            },
+            Rule::event => {
+                let (weight, count, pattern) = parse_event(step, seed)?;
+                steps.extend(std::iter::repeat_n((weight, pattern), count));
+            },
+            _ => unreachable!(),
+        }
+    }
+    Ok(steps)
+}
+
+/// Returns the event's pattern along with its weight and how many times it repeats.
+fn parse_event<T: Atom>(pair: Pair<Rule>, seed: &mut isize) -> Result<(Time, usize, Pattern<T>), ParseError> {
+    let mut inner = pair.into_inner();
+    let mut pattern = parse_raw_event(inner.next().unwrap(), seed)?;
+    let mut weight = Time::from(1);
+    let mut count = 1;
+    for modifier in inner {
+        if modifier.as_rule() == Rule::euclid {
+            let mut args = modifier.into_inner().map(|p| parse_sequence::<isize>(p, seed).map(|(_, p)| p)).collect::<Result<Vec<_>, _>>()?;
+            let r = if args.len() == 3 { args.pop().unwrap() } else { unit(0) };
+            let n = args.pop().unwrap();
+            let k = args.pop().unwrap();
+            pattern = euclid_off(k, n, r, pattern);
+            continue;
+        }
+        let modifier = modifier.into_inner().next().unwrap();
+        let rule = modifier.as_rule();
+        let number = modifier.into_inner().next();
+        match rule {
+            Rule::fast_modifier | Rule::slow_modifier => {
+                let number = number.unwrap();
+                let rate = parse_rational(number.as_str()).ok_or_else(|| ParseError::custom(number.as_span(), "invalid rate"))?;
+                pattern = if rule == Rule::fast_modifier {
+                    _fast(rate, pattern)
+                } else {
+                    _slow(rate, pattern)
+                };
+            },
+            Rule::replicate_modifier => {
+                count = match number {
+                    Some(number) => number.as_str().parse().ok().filter(|n| *n > 0).ok_or_else(|| ParseError::custom(number.as_span(), "expected a positive integer"))?,
+                    None => count + 1,
+                };
+            },
+            Rule::elongate_modifier => {
+                let number = number.unwrap();
+                weight = parse_rational(number.as_str()).filter(|w| *w > 0.into()).ok_or_else(|| ParseError::custom(number.as_span(), "expected a positive number"))?;
+            },
+            Rule::degrade_modifier => {
+                let prob = match number {
+                    Some(number) => number.as_str().parse().map_err(|_| ParseError::custom(number.as_span(), "invalid number"))?,
+                    None => 0.5,
+                };
+                pattern = _degrade_by_using(seeded_rand(new_seed(seed)), prob, pattern);
+            },
+            _ => unreachable!(),
+        }
+    }
+    Ok((weight, count, pattern))
+}
+
+fn parse_raw_event<T: Atom>(pair: Pair<Rule>, seed: &mut isize) -> Result<Pattern<T>, ParseError> {
+    let pair = pair.into_inner().next().unwrap();
+    Ok(match pair.as_rule() {
+        Rule::bracketed_pattern => {
+            let pair = pair.into_inner().next().unwrap();
+            match pair.as_rule() {
+                Rule::fast_repeat => parse_layers(pair.into_inner().next().unwrap(), seed)?,
+                Rule::cycle => parse_polymeter(pair.into_inner().next().unwrap(), Some(1.into()), seed)?,
+                Rule::polymeter => {
+                    let mut inner = pair.into_inner();
+                    let layers = inner.next().unwrap();
+                    let steps = match inner.next() {
+                        Some(steps) => {
+                            let number = steps.into_inner().next().unwrap();
+                            Some(parse_rational(number.as_str()).filter(|n| *n > 0.into()).ok_or_else(|| ParseError::custom(number.as_span(), "expected a positive number"))?)
+                        },
+                        None => None,
+                    };
+                    parse_polymeter(layers, steps, seed)?
                 },
-                "!" => todo!(),
                 _ => unreachable!(),
             }
+        },
+
+        Rule::string => {
+            let value = T::from_word(pair.as_str()).ok_or_else(|| ParseError::custom(pair.as_span(), format!("`{}` is not a valid {}", pair.as_str(), T::KIND)))?;
+            unit(value)
+        },
+
+        Rule::rest => silence(),
+
+        Rule::number => {
+            let value = T::from_number(pair.as_str()).ok_or_else(|| ParseError::custom(pair.as_span(), format!("`{}` is not a valid {}", pair.as_str(), T::KIND)))?;
+            unit(value)
+        },
+
+        _ => unreachable!(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Arc, degrade_by};
+
+    fn onsets(p: &Pattern<String>, value: &str) -> Vec<Time> {
+        let mut onsets: Vec<Time> = p(Arc { start: 0.into(), stop: 4.into() }).into_iter()
+            .filter(|e| e.value == value)
+            .map(|e| e.part.start)
+            .collect();
+        onsets.sort();
+        onsets
+    }
+
+    type Fraction = (isize, isize);
+
+    /// The events starting in the cycle as `(start, stop, value)`, with times
+    /// written as `(numerator, denominator)` relative to the start of the cycle.
+    fn cycle(s: &str, cycle: isize) -> Vec<(Fraction, Fraction, String)> {
+        let mut events = parse_pattern::<String>(s)(Arc { start: cycle.into(), stop: (cycle + 1).into() });
+        events.retain(|e| e.has_onset());
+        events.sort_by(|a, b| (a.part.start, &a.value).cmp(&(b.part.start, &b.value)));
+        events.into_iter().map(|e| {
+            let whole = e.whole.unwrap();
+            let start = whole.start - cycle;
+            let stop = whole.stop - cycle;
+            ((*start.numer(), *start.denom()), (*stop.numer(), *stop.denom()), e.value)
+        }).collect()
+    }
+
+    fn expected(events: &[(Fraction, Fraction, &str)]) -> Vec<(Fraction, Fraction, String)> {
+        events.iter().map(|(start, stop, value)| (*start, *stop, value.to_string())).collect()
+    }
+
+    #[test]
+    fn words() {
+        assert_eq!(cycle("hh_open c'maj bd:3 a.b x-1 c#", 0), expected(&[
+            ((0, 1), (1, 6), "hh_open"),
+            ((1, 6), (1, 3), "c'maj"),
+            ((1, 3), (1, 2), "bd:3"),
+            ((1, 2), (2, 3), "a.b"),
+            ((2, 3), (5, 6), "x-1"),
+            ((5, 6), (1, 1), "c#"),
+        ]));
+        assert!(try_parse_pattern::<String>("_a").is_err());
+        assert!(try_parse_pattern::<String>(".a").is_err());
+    }
+
+    #[test]
+    fn elongate() {
+        assert_eq!(cycle("a@3 b", 0), expected(&[((0, 1), (3, 4), "a"), ((3, 4), (1, 1), "b")]));
+    }
+
+    #[test]
+    fn hold() {
+        assert_eq!(cycle("a _ b", 0), expected(&[((0, 1), (2, 3), "a"), ((2, 3), (1, 1), "b")]));
+        assert!(try_parse_pattern::<String>("_ a").is_err());
+    }
+
+    #[test]
+    fn repeat() {
+        let thirds = expected(&[((0, 1), (1, 3), "a"), ((1, 3), (2, 3), "a"), ((2, 3), (1, 1), "b")]);
+        assert_eq!(cycle("a ! b", 0), thirds);
+        assert_eq!(cycle("a! b", 0), thirds);
+        assert_eq!(cycle("a!2 b", 0), thirds);
+        assert_eq!(cycle("a!3", 0), expected(&[((0, 1), (1, 3), "a"), ((1, 3), (2, 3), "a"), ((2, 3), (1, 1), "a")]));
+        assert!(try_parse_pattern::<String>("! a").is_err());
+    }
+
+    #[test]
+    fn groups() {
+        assert_eq!(cycle("a . b c", 0), expected(&[((0, 1), (1, 2), "a"), ((1, 2), (3, 4), "b"), ((3, 4), (1, 1), "c")]));
+    }
+
+    #[test]
+    fn stacks() {
+        assert_eq!(cycle("[a, b c]", 0), expected(&[((0, 1), (1, 1), "a"), ((0, 1), (1, 2), "b"), ((1, 2), (1, 1), "c")]));
+    }
+
+    #[test]
+    fn alternation() {
+        assert_eq!(cycle("<a b>", 0), expected(&[((0, 1), (1, 1), "a")]));
+        assert_eq!(cycle("<a b>", 1), expected(&[((0, 1), (1, 1), "b")]));
+        assert_eq!(cycle("<a b>", 2), expected(&[((0, 1), (1, 1), "a")]));
+    }
+
+    #[test]
+    fn polymeter() {
+        assert_eq!(cycle("{a b c}%4", 0), expected(&[((0, 1), (1, 4), "a"), ((1, 4), (1, 2), "b"), ((1, 2), (3, 4), "c"), ((3, 4), (1, 1), "a")]));
+        assert_eq!(cycle("{a b c}%4", 1), expected(&[((0, 1), (1, 4), "b"), ((1, 4), (1, 2), "c"), ((1, 2), (3, 4), "a"), ((3, 4), (1, 1), "b")]));
+        // Without a step count, the first layer sets it.
+        assert_eq!(cycle("{a b, c d e}", 1), expected(&[((0, 1), (1, 2), "a"), ((0, 1), (1, 2), "e"), ((1, 2), (1, 1), "b"), ((1, 2), (1, 1), "c")]));
+    }
+
+    #[test]
+    fn euclid() {
+        assert_eq!(cycle("a(3,8)", 0), expected(&[((0, 1), (1, 8), "a"), ((3, 8), (1, 2), "a"), ((3, 4), (7, 8), "a")]));
+        assert_eq!(cycle("a(3,8,2)", 0), expected(&[((1, 8), (1, 4), "a"), ((1, 2), (5, 8), "a"), ((3, 4), (7, 8), "a")]));
+        assert_eq!(cycle("a(<3 5>,8)", 1), expected(&[
+            ((0, 1), (1, 8), "a"), ((1, 4), (3, 8), "a"), ((3, 8), (1, 2), "a"), ((5, 8), (3, 4), "a"), ((3, 4), (7, 8), "a"),
+        ]));
+    }
+
+    #[test]
+    fn choice_with_a_fixed_seed() {
+        let values: Vec<String> = (0..8).map(|c| cycle("a|b", c).remove(0).2).collect();
+        assert_eq!(values, vec!["a", "a", "b", "b", "b", "a", "a", "b"]);
+    }
+
+    #[test]
+    fn degrade_with_a_fixed_seed() {
+        let starts: Vec<Fraction> = cycle("a*8?0.3", 0).into_iter().map(|(start, _, _)| start).collect();
+        assert_eq!(starts, vec![(1, 8), (1, 4), (3, 8), (1, 2), (5, 8), (3, 4), (7, 8)]);
+    }
+
+    #[test]
+    fn tabs_and_newlines_separate_steps() {
+        let spaced = parse_pattern::<String>("a b [c d] . e");
+        let p = parse_pattern::<String>("\ta\tb\n[c\r\n d ]\n.\te\n");
+        for value in &["a", "b", "c", "d", "e"] {
+            assert_eq!(onsets(&p, value), onsets(&spaced, value));
         }
+    }
 
-        Rule::raw_event |
-        Rule::pattern |
-        Rule::bracketed_pattern |
-        Rule::event => _parse_pattern(pair.into_inner().next().unwrap()),
+    #[test]
+    fn the_first_degrade_is_unseeded() {
+        let a = _fast(32.into(), unit("a".to_string()));
+        assert_eq!(onsets(&parse_pattern("a*32?"), "a"), onsets(&degrade_by(unit(0.5), a), "a"));
+    }
+
+    #[test]
+    fn each_degrade_gets_the_next_seed() {
+        let p = parse_pattern::<String>("[a*32?, b*32?0.3]");
+        let a = _degrade_by_using(seeded_rand(0), 0.5, _fast(32.into(), unit("a".to_string())));
+        let b = _degrade_by_using(seeded_rand(1), 0.3, _fast(32.into(), unit("b".to_string())));
+        assert_eq!(onsets(&p, "a"), onsets(&a, "a"));
+        assert_eq!(onsets(&p, "b"), onsets(&b, "b"));
+        assert_ne!(onsets(&parse_pattern("a*32? a*32?"), "a"), onsets(&parse_pattern("[a*32?]!2"), "a"));
+    }
+
+    #[test]
+    fn choices_take_a_seed_after_their_contents() {
+        let p = parse_pattern::<String>("a? | b");
+        let expected = cycle_choose_using(seeded_rand(1), vec![_degrade_by_using(seeded_rand(0), 0.5, unit("a".to_string())), unit("b".to_string())]);
+        assert_eq!(onsets(&p, "a"), onsets(&expected, "a"));
+        assert_eq!(onsets(&p, "b"), onsets(&expected, "b"));
+    }
 
-        Rule::string => unit(pair.as_str().to_string()),
+    fn error(s: &str) -> ParseError {
+        match try_parse_pattern::<String>(s) {
+            Ok(_) => panic!("{:?} parsed", s),
+            Err(e) => e,
+        }
+    }
 
-        Rule::number => todo!(),
+    #[test]
+    fn unclosed_bracket() {
+        assert_eq!(error("bd [sn"), ParseError {
+            start: (1, 7),
+            end: (1, 7),
+            expected: vec!["euclid".to_string(), "modifier".to_string()],
+            message: "expected euclid or modifier".to_string(),
+        });
+        assert_eq!(error("bd\n[sn").start, (2, 4));
+    }
+
+    #[test]
+    fn unfinished_euclid() {
+        let e = error("bd(3,");
+        assert_eq!((e.start, e.end), ((1, 6), (1, 6)));
+        assert_eq!(e.expected, vec!["step"]);
+    }
+
+    #[test]
+    fn modifier_without_a_number() {
+        let e = error("bd*");
+        assert_eq!((e.start, e.end), ((1, 4), (1, 4)));
+        assert_eq!(e.expected, vec!["number"]);
+        assert_eq!(e.to_string(), "1:4: expected number");
+    }
+
+    #[test]
+    fn errors_in_the_meaning_of_steps_cover_them() {
+        assert_eq!(error("_ a"), ParseError {
+            start: (1, 1),
+            end: (1, 2),
+            expected: vec![],
+            message: "nothing to extend".to_string(),
+        });
+        let e = error("a b@0");
+        assert_eq!((e.start, e.end, e.message.as_str()), ((1, 5), (1, 6), "expected a positive number"));
+    }
+
+    #[test]
+    #[should_panic(expected = "1:4: expected number")]
+    fn parse_pattern_panics_with_the_error() {
+        parse_pattern::<String>("bd*");
+    }
+
+    fn values<T: Atom>(s: &str) -> Result<Vec<T>, ParseError> {
+        let mut events = try_parse_pattern::<T>(s)?(Arc { start: 0.into(), stop: 1.into() });
+        events.sort_by_key(|e| e.part.start);
+        Ok(events.into_iter().map(|e| e.value).collect())
+    }
+
+    #[test]
+    fn integers() {
+        assert_eq!(values::<isize>("1 -2 3"), Ok(vec![1, -2, 3]));
+        let e = values::<isize>("1 1.5").unwrap_err();
+        assert_eq!((e.start, e.end, e.message.as_str()), ((1, 3), (1, 6), "`1.5` is not a valid integer"));
+    }
+
+    #[test]
+    fn floats() {
+        assert_eq!(values::<f32>("0.5 -1 2."), Ok(vec![0.5, -1.0, 2.0]));
+        assert_eq!(values::<f32>("bd").unwrap_err().message, "`bd` is not a valid number");
+    }
+
+    #[test]
+    fn booleans() {
+        assert_eq!(values::<bool>("t f 1 0 true false"), Ok(vec![true, false, true, false, true, false]));
+        assert!(values::<bool>("2").is_err());
+        assert!(values::<bool>("yes").is_err());
+    }
 
-        Rule::operator => unreachable!(),
+    #[test]
+    fn rationals_are_exact() {
+        assert_eq!(values::<Rational>("0.25 -1.5 3"), Ok(vec![Rational::new(1, 4), Rational::new(-3, 2), Rational::from(3)]));
+        assert_eq!(values::<Rational>("0.1"), Ok(vec![Rational::new(1, 10)]));
     }
 }
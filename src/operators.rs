@@ -0,0 +1,247 @@
+//! TidalCycles' family of pattern operators. Each takes a `Structure` saying
+//! which side's events the result keeps, so `add(Structure::Left, a, b)` is
+//! Tidal's `a |+ b`, `Structure::Right` is `a +| b` and `Structure::Both` is `a |+| b`.
+
+use std::collections::HashMap;
+
+use num::{rational::Rational, Zero};
+
+use crate::{Pattern, ControlMap, Value, apply_from_left, apply_from_right, apply_both};
+
+/// Which pattern's events the result of combining two patterns follows.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Structure {
+    Left,
+    Right,
+    Both,
+}
+
+/// Values the pattern operators can combine.
+pub trait Operand: Clone + Send + Sync + 'static {
+    fn add(self, other: Self) -> Self;
+    fn sub(self, other: Self) -> Self;
+    fn mul(self, other: Self) -> Self;
+    fn div(self, other: Self) -> Self;
+
+    /// Combines two values, preferring the right one. Tidal's `|>`.
+    fn set(self, other: Self) -> Self {
+        other
+    }
+
+    /// Combines two values, preferring the left one. Tidal's `|<`.
+    fn keep(self, _other: Self) -> Self {
+        self
+    }
+}
+
+impl Operand for f32 {
+    fn add(self, other: Self) -> Self { self + other }
+    fn sub(self, other: Self) -> Self { self - other }
+    fn mul(self, other: Self) -> Self { self * other }
+    fn div(self, other: Self) -> Self { self / other }
+}
+
+/// Division by zero leaves the left value unchanged.
+impl Operand for isize {
+    fn add(self, other: Self) -> Self { self.wrapping_add(other) }
+    fn sub(self, other: Self) -> Self { self.wrapping_sub(other) }
+    fn mul(self, other: Self) -> Self { self.wrapping_mul(other) }
+    fn div(self, other: Self) -> Self { self.checked_div(other).unwrap_or(self) }
+}
+
+/// Division by zero leaves the left value unchanged.
+impl Operand for Rational {
+    fn add(self, other: Self) -> Self { self + other }
+    fn sub(self, other: Self) -> Self { self - other }
+    fn mul(self, other: Self) -> Self { self * other }
+    fn div(self, other: Self) -> Self {
+        if other.is_zero() {
+            self
+        } else {
+            self / other
+        }
+    }
+}
+
+/// Controls are combined key by key. Keys only one side has are kept as they
+/// are. Numbers are combined arithmetically, becoming floats if either is, and
+/// adding strings joins them. Other operations on strings keep the left one.
+impl Operand for ControlMap {
+    fn add(self, other: Self) -> Self {
+        union_with(self, other, |a, b| match (a, b) {
+            (Value::String(a), Value::String(b)) => Value::String(a + &b),
+            (a, b) => numeric(a, b, <isize as Operand>::add, <f32 as Operand>::add),
+        })
+    }
+    fn sub(self, other: Self) -> Self {
+        union_with(self, other, |a, b| numeric(a, b, <isize as Operand>::sub, <f32 as Operand>::sub))
+    }
+    fn mul(self, other: Self) -> Self {
+        union_with(self, other, |a, b| numeric(a, b, <isize as Operand>::mul, <f32 as Operand>::mul))
+    }
+    fn div(self, other: Self) -> Self {
+        union_with(self, other, |a, b| numeric(a, b, <isize as Operand>::div, <f32 as Operand>::div))
+    }
+    fn set(self, other: Self) -> Self {
+        union_with(self, other, |_, b| b)
+    }
+    fn keep(self, other: Self) -> Self {
+        union_with(self, other, |a, _| a)
+    }
+}
+
+fn union_with(a: ControlMap, b: ControlMap, f: impl Fn(Value, Value) -> Value) -> ControlMap {
+    let mut result: HashMap<String, Value> = a.0;
+    for (k, b) in b.0 {
+        let v = match result.remove(&k) {
+            Some(a) => f(a, b),
+            None => b,
+        };
+        result.insert(k, v);
+    }
+    ControlMap(result)
+}
+
+fn numeric(a: Value, b: Value, int: fn(isize, isize) -> isize, float: fn(f32, f32) -> f32) -> Value {
+    match (a, b) {
+        (Value::Integer(a), Value::Integer(b)) => Value::Integer(int(a, b)),
+        (Value::Integer(a), Value::Float(b)) => Value::Float(float(a as f32, b)),
+        (Value::Float(a), Value::Integer(b)) => Value::Float(float(a, b as f32)),
+        (Value::Float(a), Value::Float(b)) => Value::Float(float(a, b)),
+        (a, _) => a,
+    }
+}
+
+fn apply<T: Operand>(structure: Structure, f: fn(T, T) -> T, lhs: Pattern<T>, rhs: Pattern<T>) -> Pattern<T> {
+    match structure {
+        Structure::Left => apply_from_left(f, lhs, rhs),
+        Structure::Right => apply_from_right(f, lhs, rhs),
+        Structure::Both => apply_both(f, lhs, rhs),
+    }
+}
+
+/// Tidal's `|+|`, `|+` and `+|`.
+pub fn add<T: Operand>(structure: Structure, lhs: Pattern<T>, rhs: Pattern<T>) -> Pattern<T> {
+    apply(structure, T::add, lhs, rhs)
+}
+
+/// Tidal's `|-|`, `|-` and `-|`.
+pub fn sub<T: Operand>(structure: Structure, lhs: Pattern<T>, rhs: Pattern<T>) -> Pattern<T> {
+    apply(structure, T::sub, lhs, rhs)
+}
+
+/// Tidal's `|*|`, `|*` and `*|`.
+pub fn mul<T: Operand>(structure: Structure, lhs: Pattern<T>, rhs: Pattern<T>) -> Pattern<T> {
+    apply(structure, T::mul, lhs, rhs)
+}
+
+/// Tidal's `|/|`, `|/` and `/|`.
+pub fn div<T: Operand>(structure: Structure, lhs: Pattern<T>, rhs: Pattern<T>) -> Pattern<T> {
+    apply(structure, T::div, lhs, rhs)
+}
+
+/// Tidal's `|>|`, `|>` and `>|`, which take values from the right.
+pub fn set<T: Operand>(structure: Structure, lhs: Pattern<T>, rhs: Pattern<T>) -> Pattern<T> {
+    apply(structure, T::set, lhs, rhs)
+}
+
+/// Tidal's `|<|`, `|<` and `<|`, which take values from the left.
+pub fn keep<T: Operand>(structure: Structure, lhs: Pattern<T>, rhs: Pattern<T>) -> Pattern<T> {
+    apply(structure, T::keep, lhs, rhs)
+}
+
+/// Tidal's `#`, the same as `set(Structure::Left, lhs, rhs)`. This is how
+/// control patterns are usually put together, as in `merge(sound(...), n(...))`.
+pub fn merge<T: Operand>(lhs: Pattern<T>, rhs: Pattern<T>) -> Pattern<T> {
+    set(Structure::Left, lhs, rhs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Arc, mini_notation::parse_pattern, s, n, unit};
+
+    fn arc(start: (isize, isize), stop: (isize, isize)) -> Arc {
+        Arc { start: Rational::new(start.0, start.1), stop: Rational::new(stop.0, stop.1) }
+    }
+
+    /// The events in the first cycle as `(whole, part, value)`, sorted by part.
+    fn first_cycle<T: Clone>(p: Pattern<T>) -> Vec<(Option<Arc>, Arc, T)> {
+        let mut events = p(arc((0, 1), (1, 1)));
+        events.sort_by_key(|e| (e.part.start, e.part.stop));
+        events.into_iter().map(|e| (e.whole, e.part, e.value)).collect()
+    }
+
+    fn controls(values: &[(&str, Value)]) -> ControlMap {
+        ControlMap(values.iter().map(|(k, v)| (k.to_string(), v.clone())).collect())
+    }
+
+    #[test]
+    fn structure_from_the_left() {
+        let half = arc((0, 1), (1, 2));
+        let second_half = arc((1, 2), (1, 1));
+        assert_eq!(first_cycle(add(Structure::Left, parse_pattern::<isize>("1 2"), parse_pattern("10 20 30"))), vec![
+            (Some(half), arc((0, 1), (1, 3)), 11),
+            (Some(half), arc((1, 3), (1, 2)), 21),
+            (Some(second_half), arc((1, 2), (2, 3)), 22),
+            (Some(second_half), arc((2, 3), (1, 1)), 32),
+        ]);
+    }
+
+    #[test]
+    fn structure_from_the_right() {
+        let middle = arc((1, 3), (2, 3));
+        assert_eq!(first_cycle(add(Structure::Right, parse_pattern::<isize>("1 2"), parse_pattern("10 20 30"))), vec![
+            (Some(arc((0, 1), (1, 3))), arc((0, 1), (1, 3)), 11),
+            (Some(middle), arc((1, 3), (1, 2)), 21),
+            (Some(middle), arc((1, 2), (2, 3)), 22),
+            (Some(arc((2, 3), (1, 1))), arc((2, 3), (1, 1)), 32),
+        ]);
+    }
+
+    #[test]
+    fn structure_from_both() {
+        let events = first_cycle(sub(Structure::Both, parse_pattern::<isize>("1 2"), parse_pattern("10 20 30")));
+        assert!(events.iter().all(|(whole, part, _)| *whole == Some(*part)));
+        assert_eq!(events.into_iter().map(|(_, part, v)| (part, v)).collect::<Vec<_>>(), vec![
+            (arc((0, 1), (1, 3)), -9),
+            (arc((1, 3), (1, 2)), -19),
+            (arc((1, 2), (2, 3)), -18),
+            (arc((2, 3), (1, 1)), -28),
+        ]);
+    }
+
+    #[test]
+    fn merge_takes_values_from_the_right_and_structure_from_the_left() {
+        let p = merge(merge(s(parse_pattern("a b")), n(parse_pattern("1"))), n(parse_pattern("2 3 4")));
+        let values: Vec<_> = first_cycle(p).into_iter().filter(|(whole, part, _)| whole.unwrap().start == part.start).map(|(_, _, v)| v).collect();
+        assert_eq!(values, vec![
+            controls(&[("s", Value::String("a".to_string())), ("n", Value::Float(2.0))]),
+            controls(&[("s", Value::String("b".to_string())), ("n", Value::Float(3.0))]),
+        ]);
+    }
+
+    #[test]
+    fn keep_takes_values_from_the_left() {
+        let p = keep(Structure::Left, merge(s(parse_pattern("a")), n(parse_pattern("1"))), merge(n(parse_pattern("2")), crate::speed(unit(2.0))));
+        assert_eq!(first_cycle(p)[0].2, controls(&[("s", Value::String("a".to_string())), ("n", Value::Float(1.0)), ("speed", Value::Float(2.0))]));
+    }
+
+    #[test]
+    fn controls_combine_numerically() {
+        let a = controls(&[("n", Value::Integer(3)), ("gain", Value::Integer(1)), ("s", Value::String("bd".to_string()))]);
+        let b = controls(&[("n", Value::Integer(2)), ("gain", Value::Float(0.5)), ("s", Value::String(":3".to_string()))]);
+        assert_eq!(a.clone().add(b.clone()), controls(&[("n", Value::Integer(5)), ("gain", Value::Float(1.5)), ("s", Value::String("bd:3".to_string()))]));
+        assert_eq!(a.clone().mul(b.clone()), controls(&[("n", Value::Integer(6)), ("gain", Value::Float(0.5)), ("s", Value::String("bd".to_string()))]));
+        assert_eq!(b.sub(a), controls(&[("n", Value::Integer(-1)), ("gain", Value::Float(-0.5)), ("s", Value::String(":3".to_string()))]));
+    }
+
+    #[test]
+    fn division_by_zero_keeps_the_left_value() {
+        assert_eq!(7isize.div(0), 7);
+        assert_eq!(Rational::new(1, 2).div(Rational::from(0)), Rational::new(1, 2));
+        let a = controls(&[("n", Value::Integer(7))]);
+        assert_eq!(a.clone().div(controls(&[("n", Value::Integer(0))])), a);
+        assert_eq!(first_cycle(div(Structure::Left, parse_pattern::<isize>("6 5"), parse_pattern("2 0"))).into_iter().map(|e| e.2).collect::<Vec<_>>(), vec![3, 5]);
+    }
+}
@@ -0,0 +1,97 @@
+//! Control pattern constructors, one for each of the standard TidalCycles and
+//! SuperDirt parameters. These are also re-exported from the crate root, apart
+//! from `unit` which would clash with the `unit` pattern constructor.
+
+use std::collections::HashMap;
+
+use crate::{Pattern, Event, ControlMap, Value};
+
+/// Turns a pattern of values into a pattern of single-entry control maps.
+fn control<T: 'static>(name: &'static str, p: Pattern<T>, to_value: fn(T) -> Value) -> Pattern<ControlMap> {
+    pattern!(move |arc| {
+        p(arc).into_iter().map(|e| {
+            let mut m = ControlMap(HashMap::new());
+            m.0.insert(name.to_string(), to_value(e.value));
+            Event {
+                whole: e.whole,
+                part: e.part,
+                value: m,
+            }
+        }).collect()
+    })
+}
+
+macro_rules! params {
+    ($($(#[$doc:meta])* $name:ident: $ty:ty => $variant:ident = $default:expr;)*) => {
+        $(
+            $(#[$doc])*
+            pub fn $name(p: Pattern<$ty>) -> Pattern<ControlMap> {
+                control(stringify!($name), p, Value::$variant)
+            }
+        )*
+
+        /// The value SuperDirt assumes for a parameter when an event doesn't
+        /// have it, or `None` if the name isn't one of the parameters here.
+        pub fn default(name: &str) -> Option<Value> {
+            match name {
+                $(stringify!($name) => Some(Value::$variant($default)),)*
+                _ => None,
+            }
+        }
+    };
+}
+
+params! {
+    /// The sample or synth to play. Unlike `sound` this doesn't split off a `:n` suffix.
+    s: String => String = String::new();
+    /// Which variation of the sample to play, or the note for synths.
+    n: f32 => Float = 0.0;
+    /// The note in semitones, where 0 is middle C.
+    note: f32 => Float = 0.0;
+    /// Playback speed, where 2 is an octave up. Negative speeds play backwards.
+    speed: f32 => Float = 1.0;
+    /// Volume, on an exponential scale where 1 leaves it unchanged.
+    gain: f32 => Float = 1.0;
+    /// Stereo position from 0 (left) to 1 (right).
+    pan: f32 => Float = 0.5;
+    /// Waveshaping distortion from 0 to 1.
+    shape: f32 => Float = 0.0;
+    /// Low pass filter cutoff frequency in Hz. 0 leaves the filter off.
+    cutoff: f32 => Float = 0.0;
+    /// Low pass filter resonance from 0 to 1.
+    resonance: f32 => Float = 0.0;
+    /// Reverb amount from 0 to 1.
+    room: f32 => Float = 0.0;
+    /// Reverb room size from 0 to 1.
+    size: f32 => Float = 0.0;
+    /// Which effects bus to play on.
+    orbit: isize => Integer = 0;
+    /// How long the note lasts relative to the event.
+    legato: f32 => Float = 1.0;
+    /// How long the note lasts in seconds.
+    sustain: f32 => Float = 0.0;
+    /// Where in the sample to start playing, from 0 to 1.
+    begin: f32 => Float = 0.0;
+    /// Where in the sample to stop playing, from 0 to 1.
+    end: f32 => Float = 1.0;
+    /// Bit crushing, from 1 (most) to 16 (least). 0 leaves it off.
+    crush: f32 => Float = 0.0;
+    /// Sample rate reduction, keeping every nth sample. 0 leaves it off.
+    coarse: isize => Integer = 0;
+    /// Formant filter, one of `a`, `e`, `i`, `o` or `u`. Empty leaves it off.
+    vowel: String => String = String::new();
+    /// Changes `speed` over the course of the note.
+    accelerate: f32 => Float = 0.0;
+    /// What `speed` is relative to: `r` for rate, `c` for cycles or `s` for seconds.
+    unit: String => String = "r".to_string();
+    /// MIDI velocity from 0 to 1.
+    velocity: f32 => Float = 1.0;
+    /// MIDI channel from 0 to 15.
+    midichan: f32 => Float = 0.0;
+    /// MIDI control change number.
+    ccn: f32 => Float = 0.0;
+    /// MIDI control change value.
+    ccv: f32 => Float = 0.0;
+    /// Sets the tempo in cycles per second from this event onwards.
+    cps: f32 => Float = 0.5;
+}
@@ -2,129 +2,626 @@ use {
     std::{
         convert::TryInto,
         collections::HashMap,
-        sync::Mutex,
-        path::Path,
+        sync::{
+            Mutex,
+            mpsc::{channel, Sender, Receiver},
+        },
+        path::{Path, PathBuf},
         io::Read,
-        fs::File, io::BufReader,
+        fs::File,
+        time::Duration,
     },
 
     num::Rational,
-    rodio::{Source, Device},
 
-    crate::{Event, Pattern, ControlMap, Arc},
+    crate::{Event, Pattern, ControlMap, Arc, Time, time_to_f64},
 };
 
-pub struct Player {
-    inner: std::sync::Arc<Mutex<InnerPlayer>>,
+mod backend;
+pub use backend::{AudioBackend, Trigger, BufferBackend, NullBackend};
+#[cfg(feature = "playback")]
+pub use backend::RodioBackend;
+mod decode;
+pub use decode::DecodeError;
+mod osc;
+pub use osc::{OscBackend, SUPERDIRT_PORT};
+mod midi;
+pub use midi::{MidiBackend, MidiOutput, MidiMessage, MemoryMidiOutput};
+mod smf;
+pub use smf::{render_midi, render_to_midi_file};
+
+/// How far ahead of the backend's clock the scheduler queries patterns, in seconds.
+const LOOKAHEAD: f64 = 0.1;
+/// How long the scheduler sleeps between queries.
+const TICK: Duration = Duration::from_millis(10);
+/// Query boundaries are rounded down to multiples of one over this many cycles
+/// so that the rationals involved stay small.
+const QUERY_RESOLUTION: isize = 1024;
+const DEFAULT_CPS: f64 = 0.5;
+
+#[cfg(feature = "playback")]
+pub struct Player<B: AudioBackend = RodioBackend> {
+    inner: std::sync::Arc<Mutex<InnerPlayer<B>>>,
+}
+#[cfg(not(feature = "playback"))]
+pub struct Player<B: AudioBackend> {
+    inner: std::sync::Arc<Mutex<InnerPlayer<B>>>,
 }
 
-struct InnerPlayer {
-    device: Device,
+struct InnerPlayer<B> {
+    backend: B,
     samples: SampleBank,
     patterns: HashMap<String, Pattern<ControlMap>>,
+    tempo: Tempo,
+    /// Whether the tempo has been anchored to the backend's clock yet.
+    started: bool,
+    /// Events which start before this cycle have already been sent to the backend.
+    scheduled_until: Time,
+    diagnostics: Option<Sender<Diagnostic>>,
+}
+impl<B: AudioBackend> InnerPlayer<B> {
+    fn tick(&mut self) {
+        if !self.started {
+            self.tempo.time = self.backend.now() + LOOKAHEAD;
+            self.tempo.cycle = time_to_f64(self.scheduled_until);
+            self.started = true;
+        }
+        let until = self.tempo.cycle_at(self.backend.now() + LOOKAHEAD);
+        if until > self.scheduled_until {
+            self.schedule(Arc { start: self.scheduled_until, stop: until });
+            self.scheduled_until = until;
+        }
+    }
+
+    fn schedule(&mut self, arc: Arc) {
+        let events = self.patterns.values().flat_map(|p| p(arc)).collect();
+        schedule_events(events, &mut self.tempo, &self.samples, &mut self.backend, self.diagnostics.as_ref());
+    }
+}
+
+/// Triggers each onset among `events` on the backend, following any tempo
+/// changes along the way.
+fn schedule_events(mut events: Vec<Event<ControlMap>>, tempo: &mut Tempo, samples: &SampleBank, backend: &mut impl AudioBackend, diagnostics: Option<&Sender<Diagnostic>>) {
+    events.retain(|e| e.has_onset());
+    events.sort_by_key(|e| e.part.start);
+    for event in events {
+        let cps: Option<f32> = event.value.0.get("cps").and_then(|v| v.clone().try_into().ok());
+        if let Some(cps) = cps {
+            tempo.set_cps(event.part.start, cps as f64);
+        }
+        let whole = event.whole_or_part();
+        let trigger = Trigger {
+            time: tempo.time_at(whole.start),
+            cycle: whole.start,
+            delta: time_to_f64(whole.stop - whole.start) / tempo.cps,
+            cps: tempo.cps,
+            value: event.value,
+        };
+        if let Err(d) = backend.trigger(&trigger, samples) {
+            if let Some(diagnostics) = diagnostics {
+                let _ = diagnostics.send(d);
+            }
+        }
+    }
 }
-impl InnerPlayer {
-    fn play_sample(&self, sample: &str, variation: usize, pan: f32) {
-        if let Some(variations) = self.samples.0.get(sample) {
-            let sound = rodio::Decoder::new(std::io::Cursor::new(variations[variation].clone())).unwrap();
-            let sound = rodio::source::Spatial::new(
-                sound,
-                [pan, 0.0, 0.0],
-                [1.0, 0.0, 0.0],
-                [0.0, 0.0, 0.0]
-            );
-            rodio::play_raw(&self.device, sound.convert_samples());
+
+/// Problems encountered during playback which don't stop it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Diagnostic {
+    /// An event named a sample which isn't in the `SampleBank`.
+    UnknownSample(String),
+    /// The backend couldn't send an event on, for instance because a socket write failed.
+    Send(String),
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Diagnostic::UnknownSample(name) => write!(f, "unknown sample `{}`", name),
+            Diagnostic::Send(e) => write!(f, "couldn't send event: {}", e),
+        }
+    }
+}
+
+/// Renders `cycles` of the pattern without an audio device, returning
+/// interleaved stereo samples along with any problems with the events, such
+/// as unknown samples.
+pub fn render(pattern: &Pattern<ControlMap>, samples: &SampleBank, cps: f64, cycles: Arc, sample_rate: u32) -> (Vec<f32>, Vec<Diagnostic>) {
+    let resampled;
+    let samples = if samples.sample_rate() == sample_rate {
+        samples
+    } else {
+        let mut bank = samples.clone();
+        bank.set_sample_rate(sample_rate);
+        resampled = bank;
+        &resampled
+    };
+    let mut backend = BufferBackend::new(sample_rate);
+    let mut tempo = Tempo {
+        time: 0.0,
+        cycle: time_to_f64(cycles.start),
+        cps: DEFAULT_CPS,
+    };
+    tempo.set_cps(cycles.start, cps);
+    let (sender, diagnostics) = channel();
+    schedule_events(pattern(cycles), &mut tempo, samples, &mut backend, Some(&sender));
+    let frames = backend.frame_at(tempo.time_at(cycles.stop));
+    (backend.render(frames as usize), diagnostics.try_iter().collect())
+}
+
+/// Renders `cycles` of the pattern to a 32 bit float stereo WAV file. See `render`.
+pub fn render_to_wav(path: impl AsRef<Path>, pattern: &Pattern<ControlMap>, samples: &SampleBank, cps: f64, cycles: Arc, sample_rate: u32) -> Result<Vec<Diagnostic>, hound::Error> {
+    let spec = hound::WavSpec {
+        channels: 2,
+        sample_rate,
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+    };
+    let mut writer = hound::WavWriter::create(path, spec)?;
+    let (rendered, diagnostics) = render(pattern, samples, cps, cycles, sample_rate);
+    for sample in rendered {
+        writer.write_sample(sample)?;
+    }
+    writer.finalize()?;
+    Ok(diagnostics)
+}
+
+/// Maps between cycles and seconds on the audio clock. The mapping is anchored
+/// at the point of the last tempo change so that changing tempo doesn't make
+/// the cycle position jump.
+#[derive(Copy, Clone, Debug)]
+struct Tempo {
+    time: f64,
+    cycle: f64,
+    cps: f64,
+}
+impl Tempo {
+    fn time_at(&self, cycle: Time) -> f64 {
+        self.time + (time_to_f64(cycle) - self.cycle) / self.cps
+    }
+
+    fn cycle_at(&self, time: f64) -> Time {
+        let cycle = self.cycle + (time - self.time) * self.cps;
+        Rational::new((cycle * QUERY_RESOLUTION as f64).floor() as isize, QUERY_RESOLUTION)
+    }
+
+    /// Changes tempo from the given cycle onwards. Non-positive or non-finite
+    /// values are ignored.
+    fn set_cps(&mut self, cycle: Time, cps: f64) {
+        if cps > 0.0 && cps.is_finite() {
+            self.time = self.time_at(cycle);
+            self.cycle = time_to_f64(cycle);
+            self.cps = cps;
         }
     }
 }
 
-pub struct SampleBank(HashMap<String, Vec<std::sync::Arc<[u8]>>>);
+/// A sample decoded to interleaved stereo frames at the bank's sample rate.
+/// The encoded data is kept so the sample can be decoded again if the rate changes.
+#[derive(Clone)]
+struct Sample {
+    data: std::sync::Arc<[u8]>,
+    frames: std::sync::Arc<[f32]>,
+}
+impl Sample {
+    fn decode(data: std::sync::Arc<[u8]>, sample_rate: u32) -> Result<Self, DecodeError> {
+        let frames = decode::decode(&data, sample_rate)?.into();
+        Ok(Self {
+            data,
+            frames,
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct SampleBank {
+    sample_rate: u32,
+    sets: HashMap<String, Vec<Sample>>,
+}
+impl Default for SampleBank {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 impl SampleBank {
     pub fn new() -> Self {
-        Self(HashMap::new())
+        Self::with_sample_rate(44100)
     }
 
-    pub fn add_sample_set(&mut self, name: impl AsRef<str>, samples: Vec<Vec<u8>>) {
-        self.0.insert(
-            name.as_ref().to_string(),
-            samples.into_iter().map(|s| std::sync::Arc::from(s)).collect()
-        );
+    pub fn with_sample_rate(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            sets: HashMap::new(),
+        }
     }
 
-    pub fn add_sample_set_from_dir(&mut self, name: impl AsRef<str>, path: impl AsRef<Path>) {
-        let mut samples = vec![];
-        let mut paths:Vec<_> = std::fs::read_dir(path).unwrap().into_iter().map(|p| p.unwrap().path()).collect();
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Decodes every sample again at the new rate, if it differs from the current one.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        if sample_rate != self.sample_rate {
+            self.sample_rate = sample_rate;
+            for set in self.sets.values_mut() {
+                for sample in set.iter_mut() {
+                    // These decoded successfully once already.
+                    *sample = Sample::decode(sample.data.clone(), sample_rate).unwrap();
+                }
+            }
+        }
+    }
+
+    /// Decodes and adds the samples. Nothing is added if any of them fails to decode.
+    pub fn add_sample_set(&mut self, name: impl AsRef<str>, samples: Vec<Vec<u8>>) -> Result<(), DecodeError> {
+        let samples = samples.into_iter().map(|s| Sample::decode(s.into(), self.sample_rate)).collect::<Result<_, _>>()?;
+        self.sets.insert(name.as_ref().to_string(), samples);
+        Ok(())
+    }
+
+    /// Adds the audio files in the directory, in file name order. Files which
+    /// can't be loaded are skipped and listed in the report. An error is only
+    /// returned if the directory itself can't be read.
+    pub fn add_sample_set_from_dir(&mut self, name: impl AsRef<str>, path: impl AsRef<Path>) -> std::io::Result<LoadReport> {
+        let path = path.as_ref();
+        let mut report = LoadReport::default();
+        let mut paths = vec![];
+        for entry in std::fs::read_dir(path)? {
+            match entry {
+                Ok(entry) => paths.push(entry.path()),
+                Err(e) => report.skipped.push((path.to_path_buf(), SkipReason::Io(e))),
+            }
+        }
         paths.sort();
+        let mut samples = vec![];
         for p in paths {
-            let mut file = File::open(p).unwrap();
-            let mut data = vec![];
-            file.read_to_end(&mut data);
-            samples.push(data);
+            match self.load_sample(&p) {
+                Ok(sample) => {
+                    samples.push(sample);
+                    report.loaded.push(p);
+                },
+                Err(reason) => report.skipped.push((p, reason)),
+            }
         }
-        self.add_sample_set(name, samples);
+        if !samples.is_empty() {
+            self.sets.insert(name.as_ref().to_string(), samples);
+        }
+        Ok(report)
     }
 
-    pub fn add_sample_sets_from_dir(&mut self, path: impl AsRef<Path>) {
-        for p in std::fs::read_dir(path).unwrap() {
-            let p = p.unwrap();
-            if p.path().is_dir() {
-                self.add_sample_set_from_dir(p.file_name().into_string().unwrap(), p.path());
+    /// Adds a sample set for each subdirectory, named after the directory, in
+    /// the same way as `add_sample_set_from_dir`.
+    pub fn add_sample_sets_from_dir(&mut self, path: impl AsRef<Path>) -> std::io::Result<LoadReport> {
+        let path = path.as_ref();
+        let mut report = LoadReport::default();
+        for entry in std::fs::read_dir(path)? {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    report.skipped.push((path.to_path_buf(), SkipReason::Io(e)));
+                    continue;
+                },
+            };
+            let p = entry.path();
+            if !p.is_dir() {
+                report.skipped.push((p, SkipReason::NotADirectory));
+                continue;
+            }
+            match entry.file_name().into_string() {
+                Ok(name) => match self.add_sample_set_from_dir(name, &p) {
+                    Ok(r) => report.extend(r),
+                    Err(e) => report.skipped.push((p, SkipReason::Io(e))),
+                },
+                Err(_) => report.skipped.push((p, SkipReason::InvalidName)),
             }
         }
+        Ok(report)
+    }
+
+    fn load_sample(&self, path: &Path) -> Result<Sample, SkipReason> {
+        if path.is_dir() {
+            return Err(SkipReason::NotAFile);
+        }
+        let extension = path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase());
+        if !extension.is_some_and(|e| AUDIO_EXTENSIONS.contains(&e.as_str())) {
+            return Err(SkipReason::UnsupportedFormat);
+        }
+        let mut data = vec![];
+        File::open(path).and_then(|mut f| f.read_to_end(&mut data)).map_err(SkipReason::Io)?;
+        Sample::decode(data.into(), self.sample_rate).map_err(SkipReason::Decode)
     }
 }
 
+/// File extensions which `SampleBank` will try to load from directories.
+const AUDIO_EXTENSIONS: &[&str] = &["wav", "wave", "flac", "ogg"];
 
-impl Player {
+/// Which files were loaded into a `SampleBank` and which were skipped.
+#[derive(Debug, Default)]
+pub struct LoadReport {
+    pub loaded: Vec<PathBuf>,
+    pub skipped: Vec<(PathBuf, SkipReason)>,
+}
+impl LoadReport {
+    pub fn extend(&mut self, other: LoadReport) {
+        self.loaded.extend(other.loaded);
+        self.skipped.extend(other.skipped);
+    }
+}
+
+#[derive(Debug)]
+pub enum SkipReason {
+    NotAFile,
+    NotADirectory,
+    /// The directory name isn't valid unicode so it can't be used as a sample name.
+    InvalidName,
+    UnsupportedFormat,
+    Io(std::io::Error),
+    Decode(DecodeError),
+}
+
+impl std::fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SkipReason::NotAFile => write!(f, "not a file"),
+            SkipReason::NotADirectory => write!(f, "not a directory"),
+            SkipReason::InvalidName => write!(f, "name is not valid unicode"),
+            SkipReason::UnsupportedFormat => write!(f, "not a supported audio format"),
+            SkipReason::Io(e) => write!(f, "{}", e),
+            SkipReason::Decode(e) => write!(f, "couldn't decode: {}", e),
+        }
+    }
+}
+
+
+#[cfg(feature = "playback")]
+impl Player<RodioBackend> {
+    /// Creates a player on the default output device.
     pub fn new(samples: SampleBank) -> Self {
-        let device = rodio::default_output_device().unwrap();
+        Self::with_backend(samples, RodioBackend::new().expect("no audio output device"))
+    }
+}
+
+impl<B: AudioBackend> Player<B> {
+    pub fn with_backend(mut samples: SampleBank, backend: B) -> Self {
+        if let Some(sample_rate) = backend.sample_rate() {
+            samples.set_sample_rate(sample_rate);
+        }
 
         Self {
             inner: std::sync::Arc::new(Mutex::new(InnerPlayer {
-                device,
-                samples: samples,
+                backend,
+                samples,
                 patterns: HashMap::new(),
+                tempo: Tempo {
+                    time: 0.0,
+                    cycle: 0.0,
+                    cps: DEFAULT_CPS,
+                },
+                started: false,
+                scheduled_until: 0.into(),
+                diagnostics: None,
             }))
         }
     }
 
+    /// Runs `f` with the backend, for instance to inspect what a `NullBackend` recorded.
+    pub fn backend<R>(&self, f: impl FnOnce(&mut B) -> R) -> R {
+        f(&mut self.inner.lock().unwrap().backend)
+    }
+
+    /// Returns a channel which receives problems encountered during playback,
+    /// such as events naming unknown samples. Only the most recently returned
+    /// receiver gets them.
+    pub fn diagnostics(&self) -> Receiver<Diagnostic> {
+        let (sender, receiver) = channel();
+        self.inner.lock().unwrap().diagnostics = Some(sender);
+        receiver
+    }
+
     pub fn set_pattern(&self, name: impl AsRef<str>, pattern: Pattern<ControlMap>) {
         let patterns = &mut self.inner.lock().unwrap().patterns;
         patterns.insert(name.as_ref().to_string(), pattern);
     }
 
+    /// Sets the tempo in cycles per second. The change takes effect from the
+    /// first cycle position that hasn't been scheduled yet. Patterns can also
+    /// change tempo with the `cps` control, so a ramp is just a pattern of
+    /// `cps` values.
+    pub fn set_cps(&self, cps: f64) {
+        let mut player = self.inner.lock().unwrap();
+        let at = player.scheduled_until;
+        player.tempo.set_cps(at, cps);
+    }
+
+    /// Writes `cycles` of the current patterns to a Standard MIDI File at the
+    /// current tempo, with a track for each pattern. See `render_midi`.
+    pub fn render_to_midi_file(&self, path: impl AsRef<Path>, cycles: Arc) -> std::io::Result<()> {
+        let player = self.inner.lock().unwrap();
+        let mut patterns: Vec<_> = player.patterns.iter().collect();
+        patterns.sort_by_key(|(name, _)| name.as_str());
+        render_to_midi_file(path, patterns, player.tempo.cps, cycles)
+    }
+
+    /// Sends the backend everything up to a little way past its current time.
+    /// Cycle zero starts shortly after the first call. `start_playback` calls
+    /// this repeatedly but it can also be called directly to drive the player
+    /// by hand.
+    pub fn tick(&self) {
+        self.inner.lock().unwrap().tick();
+    }
+
     pub fn start_playback(&self) {
         let player = self.inner.clone();
         std::thread::spawn(move || {
-            let pattern_sample_granularity: Rational = (1, 1).into();
-            let mut current: Rational = 0.into();
-            let mut pending_events:Vec<Event<ControlMap>> = vec![];
             loop {
-                let next = current + pattern_sample_granularity;
-                {
-                    let player = player.lock().unwrap();
-                    for pattern in player.patterns.values() {
-                        pending_events.extend(pattern(Arc {
-                            start: current,
-                            stop: next,
-                        }));
-                    }
-                    pending_events.sort_by_key(|e| e.part.start);
-                }
-                for event in pending_events.drain(..) {
-                    let gap = ((event.part.start - current) * 1000).to_integer().max(0) as u64 * 2;
-                    //FIXME: check that we didn't wake early
-                    std::thread::sleep(std::time::Duration::from_millis(gap));
-                    current = event.part.start;
-                    let sample:String = event.value.0["s"].clone().try_into().unwrap();
-                    let variation:isize = event.value.0["n"].clone().try_into().unwrap();
-                    let pan: f32 = event.value.0.get("pan").and_then(|v| v.clone().try_into().ok()).unwrap_or(0.5);
-                    player.lock().unwrap().play_sample(&sample, variation as usize, pan);
-                }
-                let gap = ((next - current) * 1000).to_integer().max(0) as u64 * 2;
-                std::thread::sleep(std::time::Duration::from_millis(gap));
-                current = next;
+                player.lock().unwrap().tick();
+                std::thread::sleep(TICK);
             }
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mini_notation::parse_pattern, merge, cps};
+
+    fn player(pattern: Pattern<ControlMap>) -> Player<NullBackend> {
+        let player = Player::with_backend(SampleBank::new(), NullBackend::new());
+        player.set_pattern("d1", pattern);
+        player
+    }
+
+    /// Ticks every 10ms of backend time until `seconds` have passed.
+    fn run(player: &Player<NullBackend>, seconds: f64) {
+        let ticks = (seconds * 100.0).round() as usize;
+        for _ in 0..ticks {
+            player.tick();
+            player.backend(|b| b.advance(0.01));
+        }
+    }
+
+    fn sample_name(trigger: &Trigger) -> &str {
+        match &trigger.value.0["s"] {
+            crate::Value::String(s) => s,
+            v => panic!("unexpected s {:?}", v),
+        }
+    }
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn triggers_onsets_a_lookahead_after_the_first_tick() {
+        let player = player(crate::sound(parse_pattern("bd sn")));
+        player.set_cps(1.0);
+        run(&player, 1.55);
+        let triggers = player.backend(|b| b.take_triggers());
+        let names: Vec<_> = triggers.iter().map(sample_name).collect();
+        assert_eq!(names, vec!["bd", "sn", "bd", "sn"]);
+        for (i, trigger) in triggers.iter().enumerate() {
+            assert_close(trigger.time, 0.1 + 0.5 * i as f64);
+            assert_eq!(trigger.cycle, Rational::new(i as isize, 2));
+            assert_close(trigger.delta, 0.5);
+            assert_close(trigger.cps, 1.0);
+        }
+    }
+
+    #[test]
+    fn events_are_only_triggered_once() {
+        // Each tick queries a small slice, so most events come back as
+        // several fragments; only the one with the onset is played.
+        let player = player(crate::sound(parse_pattern("bd")));
+        run(&player, 6.05);
+        let cycles: Vec<_> = player.backend(|b| b.take_triggers()).iter().map(|t| t.cycle).collect();
+        assert_eq!(cycles, vec![0.into(), 1.into(), 2.into(), 3.into()]);
+    }
+
+    #[test]
+    fn cps_controls_change_tempo_from_their_event() {
+        let player = player(merge(crate::sound(parse_pattern("bd*2")), cps(parse_pattern("<1 2>"))));
+        run(&player, 1.55);
+        let triggers = player.backend(|b| b.take_triggers());
+        let expected = [(0.1, 0.5, 1.0), (0.6, 0.5, 1.0), (1.1, 0.25, 2.0), (1.35, 0.25, 2.0), (1.6, 0.5, 1.0)];
+        assert_eq!(triggers.len(), expected.len());
+        for (trigger, (time, delta, cps)) in triggers.iter().zip(expected.iter()) {
+            assert_close(trigger.time, *time);
+            assert_close(trigger.delta, *delta);
+            assert_close(trigger.cps, *cps);
+        }
+    }
+
+    #[test]
+    fn set_cps_changes_tempo_from_what_is_not_yet_scheduled() {
+        let player = player(crate::sound(parse_pattern("bd sn")));
+        player.set_cps(1.0);
+        run(&player, 0.55);
+        player.set_cps(2.0);
+        run(&player, 1.0);
+        let triggers = player.backend(|b| b.take_triggers());
+        let cycles: Vec<_> = triggers.iter().map(|t| t.cycle).collect();
+        assert_eq!(cycles, vec![0.into(), Rational::new(1, 2), 1.into(), Rational::new(3, 2), 2.into(), Rational::new(5, 2)]);
+        assert_close(triggers[1].time, 0.6);
+        assert_close(triggers[1].delta, 0.5);
+        for pair in triggers[2..].windows(2) {
+            assert_close(pair[1].time - pair[0].time, 0.25);
+        }
+        for trigger in &triggers[2..] {
+            assert_close(trigger.delta, 0.25);
+            assert_close(trigger.cps, 2.0);
+        }
+    }
+
+    fn wav() -> Vec<u8> {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 44100,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut data = std::io::Cursor::new(vec![]);
+        let mut writer = hound::WavWriter::new(&mut data, spec).unwrap();
+        writer.write_sample(0i16).unwrap();
+        writer.finalize().unwrap();
+        data.into_inner()
+    }
+
+    #[test]
+    fn loading_a_directory_reports_skipped_files() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.wav"), wav()).unwrap();
+        std::fs::write(dir.path().join("b.txt"), "not a sample").unwrap();
+        std::fs::write(dir.path().join("c.wav"), "RIFF but not really").unwrap();
+        std::fs::create_dir(dir.path().join("d")).unwrap();
+
+        let mut bank = SampleBank::new();
+        let report = bank.add_sample_set_from_dir("x", dir.path()).unwrap();
+        assert_eq!(report.loaded, vec![dir.path().join("a.wav")]);
+        let skipped: Vec<_> = report.skipped.iter().map(|(p, reason)| (p.file_name().unwrap().to_str().unwrap(), reason)).collect();
+        assert!(matches!(skipped[..], [
+            ("b.txt", SkipReason::UnsupportedFormat),
+            ("c.wav", SkipReason::Decode(DecodeError::UnrecognizedFormat)),
+            ("d", SkipReason::NotAFile),
+        ]), "{:?}", skipped);
+        assert_eq!(bank.sets["x"].len(), 1);
+    }
+
+    #[test]
+    fn loading_sets_from_subdirectories() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("bd")).unwrap();
+        std::fs::write(dir.path().join("bd").join("1.wav"), wav()).unwrap();
+        std::fs::write(dir.path().join("bd").join("2.flac"), "fLaC nonsense").unwrap();
+        std::fs::create_dir(dir.path().join("empty")).unwrap();
+        std::fs::write(dir.path().join("readme.txt"), "").unwrap();
+
+        let mut bank = SampleBank::new();
+        let report = bank.add_sample_sets_from_dir(dir.path()).unwrap();
+        assert_eq!(report.loaded, vec![dir.path().join("bd").join("1.wav")]);
+        let mut skipped: Vec<_> = report.skipped.iter().map(|(p, reason)| (p.strip_prefix(dir.path()).unwrap().to_path_buf(), reason.to_string())).collect();
+        skipped.sort();
+        assert_eq!(skipped[0].0, Path::new("bd").join("2.flac"));
+        assert!(skipped[0].1.starts_with("couldn't decode: invalid FLAC"), "{}", skipped[0].1);
+        assert_eq!(skipped[1], (PathBuf::from("readme.txt"), "not a directory".to_string()));
+        assert_eq!(skipped.len(), 2);
+        assert_eq!(bank.sets.keys().collect::<Vec<_>>(), vec!["bd"]);
+        assert!(bank.add_sample_sets_from_dir(dir.path().join("missing")).is_err());
+    }
+
+    #[test]
+    fn render_returns_diagnostics() {
+        let mut samples = SampleBank::new();
+        samples.add_sample_set("bd", vec![wav()]).unwrap();
+        let pattern = crate::sound(parse_pattern("bd nope bd nope"));
+        let cycle = Arc { start: 0.into(), stop: 1.into() };
+        let (rendered, diagnostics) = render(&pattern, &samples, 1.0, cycle, 44100);
+        assert_eq!(rendered.len(), 2 * 44100);
+        assert_eq!(diagnostics, vec![Diagnostic::UnknownSample("nope".to_string()); 2]);
+
+        let dir = tempfile::tempdir().unwrap();
+        let diagnostics = render_to_wav(dir.path().join("out.wav"), &pattern, &samples, 1.0, cycle, 44100).unwrap();
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(hound::WavReader::open(dir.path().join("out.wav")).unwrap().duration(), 44100);
+    }
+}
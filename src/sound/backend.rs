@@ -0,0 +1,374 @@
+use {
+    std::sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{channel, Sender, Receiver},
+    },
+
+    crate::{ControlMap, Value, Time},
+    super::{SampleBank, Diagnostic},
+};
+#[cfg(feature = "playback")]
+use {
+    std::time::Duration,
+    rodio::{Source, DeviceTrait},
+};
+
+/// Something a `Player` can send events to.
+pub trait AudioBackend: Send + 'static {
+    /// The sample rate samples should be decoded at, for backends which play them.
+    fn sample_rate(&self) -> Option<u32>;
+
+    /// The current time in seconds on the clock events are scheduled against.
+    /// This must never go backwards.
+    fn now(&self) -> f64;
+
+    /// Called ahead of time for each event onset, in order.
+    fn trigger(&mut self, trigger: &Trigger, samples: &SampleBank) -> Result<(), Diagnostic>;
+}
+
+/// An event onset along with when it should happen.
+#[derive(Clone, Debug)]
+pub struct Trigger {
+    /// When the event starts, in seconds on the backend's clock.
+    pub time: f64,
+    /// The cycle position the event starts at.
+    pub cycle: Time,
+    /// How long the whole event lasts, in seconds.
+    pub delta: f64,
+    /// The tempo at the time of the event.
+    pub cps: f64,
+    pub value: ControlMap,
+}
+
+/// Plays samples on the default output device.
+#[cfg(feature = "playback")]
+pub struct RodioBackend {
+    voices: Sender<Voice>,
+    clock: AudioClock,
+}
+#[cfg(feature = "playback")]
+impl RodioBackend {
+    /// Starts an output stream on the default device, or returns `None` if there isn't one.
+    pub fn new() -> Option<Self> {
+        let device = rodio::default_output_device()?;
+        let sample_rate = device.default_output_format().map(|f| f.sample_rate.0).unwrap_or(44100);
+        let clock = AudioClock::new(sample_rate);
+        let (voices, incoming) = channel();
+        rodio::play_raw(&device, Mixer::new(incoming, clock.clone()));
+        Some(Self {
+            voices,
+            clock,
+        })
+    }
+}
+
+#[cfg(feature = "playback")]
+impl AudioBackend for RodioBackend {
+    fn sample_rate(&self) -> Option<u32> {
+        Some(self.clock.sample_rate)
+    }
+
+    fn now(&self) -> f64 {
+        self.clock.now()
+    }
+
+    fn trigger(&mut self, trigger: &Trigger, samples: &SampleBank) -> Result<(), Diagnostic> {
+        if let Some(voice) = sample_voice(trigger, samples, &self.clock)? {
+            // The mixer is gone once the output stream is, in which case there's nothing to play to.
+            let _ = self.voices.send(voice);
+        }
+        Ok(())
+    }
+}
+
+/// Mixes samples into memory instead of playing them. Time only passes as
+/// frames are rendered.
+pub struct BufferBackend {
+    voices: Sender<Voice>,
+    clock: AudioClock,
+    mixer: Mixer,
+}
+impl BufferBackend {
+    pub fn new(sample_rate: u32) -> Self {
+        let clock = AudioClock::new(sample_rate);
+        let (voices, incoming) = channel();
+        Self {
+            voices,
+            mixer: Mixer::new(incoming, clock.clone()),
+            clock,
+        }
+    }
+
+    /// Mixes the next `frames` frames, returning interleaved stereo samples.
+    pub fn render(&mut self, frames: usize) -> Vec<f32> {
+        (&mut self.mixer).take(frames * 2).collect()
+    }
+
+    pub(super) fn frame_at(&self, time: f64) -> u64 {
+        self.clock.frame_at(time)
+    }
+}
+
+impl AudioBackend for BufferBackend {
+    fn sample_rate(&self) -> Option<u32> {
+        Some(self.clock.sample_rate)
+    }
+
+    fn now(&self) -> f64 {
+        self.clock.now()
+    }
+
+    fn trigger(&mut self, trigger: &Trigger, samples: &SampleBank) -> Result<(), Diagnostic> {
+        if let Some(voice) = sample_voice(trigger, samples, &self.clock)? {
+            // The mixer lives as long as we do.
+            self.voices.send(voice).unwrap();
+        }
+        Ok(())
+    }
+}
+
+/// Records triggers without playing anything. Its clock only moves when
+/// `advance` is called, so scheduling can be driven deterministically.
+#[derive(Default)]
+pub struct NullBackend {
+    time: f64,
+    triggers: Vec<Trigger>,
+}
+impl NullBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn advance(&mut self, seconds: f64) {
+        self.time += seconds.max(0.0);
+    }
+
+    pub fn triggers(&self) -> &[Trigger] {
+        &self.triggers
+    }
+
+    pub fn take_triggers(&mut self) -> Vec<Trigger> {
+        std::mem::take(&mut self.triggers)
+    }
+}
+
+impl AudioBackend for NullBackend {
+    fn sample_rate(&self) -> Option<u32> {
+        None
+    }
+
+    fn now(&self) -> f64 {
+        self.time
+    }
+
+    fn trigger(&mut self, trigger: &Trigger, _samples: &SampleBank) -> Result<(), Diagnostic> {
+        self.triggers.push(trigger.clone());
+        Ok(())
+    }
+}
+
+/// Builds a voice for the sample named by the trigger's `s`, if it has one.
+/// Like SuperDirt, `n` is rounded down and wraps around the number of variations.
+fn sample_voice(trigger: &Trigger, samples: &SampleBank, clock: &AudioClock) -> Result<Option<Voice>, Diagnostic> {
+    let sample = match trigger.value.0.get("s") {
+        Some(Value::String(s)) => s,
+        _ => return Ok(None),
+    };
+    let n = trigger.value.0.get("n").and_then(Value::as_f64).unwrap_or(0.0);
+    let pan = trigger.value.0.get("pan").and_then(Value::as_f64).unwrap_or(0.5) as f32;
+    let variations = samples.sets.get(sample).filter(|v| !v.is_empty()).ok_or_else(|| Diagnostic::UnknownSample(sample.to_string()))?;
+    let variation = (n.floor() as isize).rem_euclid(variations.len() as isize) as usize;
+    Ok(Some(Voice {
+        start: clock.frame_at(trigger.time),
+        frames: variations[variation].frames.clone(),
+        position: 0,
+        gains: [(2.0 * (1.0 - pan)).min(1.0), (2.0 * pan).min(1.0)],
+    }))
+}
+
+/// Counts the frames the mixer has produced. This is the monotonic clock that
+/// everything is scheduled against, so events can't drift relative to the audio.
+#[derive(Clone)]
+struct AudioClock {
+    frames: std::sync::Arc<AtomicU64>,
+    sample_rate: u32,
+}
+impl AudioClock {
+    fn new(sample_rate: u32) -> Self {
+        Self {
+            frames: std::sync::Arc::new(AtomicU64::new(0)),
+            sample_rate,
+        }
+    }
+
+    fn now(&self) -> f64 {
+        self.frames.load(Ordering::Relaxed) as f64 / self.sample_rate as f64
+    }
+
+    fn frame_at(&self, time: f64) -> u64 {
+        (time * self.sample_rate as f64).round().max(0.0) as u64
+    }
+}
+
+/// A sound which starts playing at a particular frame.
+struct Voice {
+    start: u64,
+    /// Interleaved stereo samples at the mixer's sample rate.
+    frames: std::sync::Arc<[f32]>,
+    position: usize,
+    gains: [f32; 2],
+}
+
+/// Mixes voices into a single stereo stream, starting each one on exactly the
+/// frame it was scheduled for. Voices which arrive late start immediately.
+struct Mixer {
+    incoming: Receiver<Voice>,
+    pending: Vec<Voice>,
+    playing: Vec<Voice>,
+    clock: AudioClock,
+    frame: u64,
+    right: Option<f32>,
+}
+impl Mixer {
+    fn new(incoming: Receiver<Voice>, clock: AudioClock) -> Self {
+        Self {
+            incoming,
+            pending: vec![],
+            playing: vec![],
+            frame: clock.frames.load(Ordering::Relaxed),
+            clock,
+            right: None,
+        }
+    }
+
+    fn next_frame(&mut self) -> [f32; 2] {
+        let mut received = false;
+        while let Ok(voice) = self.incoming.try_recv() {
+            self.pending.push(voice);
+            received = true;
+        }
+        if received {
+            self.pending.sort_by_key(|v| std::cmp::Reverse(v.start));
+        }
+        while self.pending.last().is_some_and(|v| v.start <= self.frame) {
+            self.playing.push(self.pending.pop().unwrap());
+        }
+
+        let mut frame = [0.0, 0.0];
+        self.playing.retain_mut(|voice| {
+            match voice.frames.get(voice.position..voice.position + 2) {
+                Some([l, r]) => {
+                    frame[0] += l * voice.gains[0];
+                    frame[1] += r * voice.gains[1];
+                    voice.position += 2;
+                    true
+                },
+                _ => false,
+            }
+        });
+        self.frame += 1;
+        self.clock.frames.store(self.frame, Ordering::Relaxed);
+        frame
+    }
+}
+
+impl Iterator for Mixer {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if let Some(r) = self.right.take() {
+            Some(r)
+        } else {
+            let [l, r] = self.next_frame();
+            self.right = Some(r);
+            Some(l)
+        }
+    }
+}
+
+#[cfg(feature = "playback")]
+impl Source for Mixer {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        2
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.clock.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    /// A one frame mono WAV file.
+    fn wav(sample: i16) -> Vec<u8> {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 44100,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut data = std::io::Cursor::new(vec![]);
+        let mut writer = hound::WavWriter::new(&mut data, spec).unwrap();
+        writer.write_sample(sample).unwrap();
+        writer.finalize().unwrap();
+        data.into_inner()
+    }
+
+    fn trigger(controls: &[(&str, Value)]) -> Trigger {
+        Trigger {
+            time: 1.0,
+            cycle: 0.into(),
+            delta: 1.0,
+            cps: 1.0,
+            value: ControlMap(controls.iter().map(|(k, v)| (k.to_string(), v.clone())).collect::<HashMap<_, _>>()),
+        }
+    }
+
+    /// Which of the `bd` variations the trigger plays, by its first sample.
+    fn variation(n: Value) -> f32 {
+        let mut samples = SampleBank::new();
+        samples.add_sample_set("bd", vec![wav(0), wav(8192), wav(16384)]).unwrap();
+        let voice = sample_voice(&trigger(&[("s", Value::String("bd".to_string())), ("n", n)]), &samples, &AudioClock::new(44100));
+        let voice = voice.unwrap().unwrap();
+        assert_eq!(voice.start, 44100);
+        voice.frames[0] * 4.0
+    }
+
+    #[test]
+    fn n_picks_the_variation() {
+        assert_eq!(variation(Value::Integer(0)), 0.0);
+        assert_eq!(variation(Value::Integer(2)), 2.0);
+        assert_eq!(variation(Value::Float(1.9)), 1.0);
+    }
+
+    #[test]
+    fn n_wraps_around() {
+        assert_eq!(variation(Value::Integer(5)), 2.0);
+        assert_eq!(variation(Value::Integer(-1)), 2.0);
+        assert_eq!(variation(Value::Float(-0.5)), 2.0);
+        assert_eq!(variation(Value::Integer(-3)), 0.0);
+    }
+
+    #[test]
+    fn unknown_samples() {
+        let samples = SampleBank::new();
+        let clock = AudioClock::new(44100);
+        assert!(matches!(
+            sample_voice(&trigger(&[("s", Value::String("nope".to_string()))]), &samples, &clock),
+            Err(Diagnostic::UnknownSample(name)) if name == "nope"
+        ));
+        assert!(matches!(sample_voice(&trigger(&[("note", Value::Float(0.0))]), &samples, &clock), Ok(None)));
+    }
+}
@@ -0,0 +1,138 @@
+use std::io::Cursor;
+
+/// Why a sample couldn't be decoded.
+#[derive(Debug)]
+pub enum DecodeError {
+    /// The data isn't WAV, FLAC or Ogg Vorbis.
+    UnrecognizedFormat,
+    /// The data starts like the named format but isn't valid.
+    Invalid(&'static str, String),
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::UnrecognizedFormat => write!(f, "unrecognized format"),
+            DecodeError::Invalid(format, e) => write!(f, "invalid {}: {}", format, e),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Decodes a WAV, FLAC or Ogg Vorbis file to interleaved stereo at `sample_rate`.
+/// Mono is played on both sides and anything past the first two channels is dropped.
+pub(super) fn decode(data: &[u8], sample_rate: u32) -> Result<Vec<f32>, DecodeError> {
+    let (channels, rate, samples) = if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WAVE") {
+        decode_wav(data).map_err(|e| DecodeError::Invalid("WAV", e.to_string()))?
+    } else if data.starts_with(b"fLaC") {
+        decode_flac(data).map_err(|e| DecodeError::Invalid("FLAC", e.to_string()))?
+    } else if data.starts_with(b"OggS") {
+        decode_vorbis(data).map_err(|e| DecodeError::Invalid("Ogg Vorbis", e.to_string()))?
+    } else {
+        return Err(DecodeError::UnrecognizedFormat);
+    };
+    if channels == 0 || rate == 0 {
+        return Err(DecodeError::Invalid("audio", "no channels or a sample rate of zero".to_string()));
+    }
+    let frames: Vec<[f32; 2]> = samples.chunks_exact(channels).map(|frame| {
+        [frame[0], frame[channels.min(2) - 1]]
+    }).collect();
+    Ok(resample(&frames, rate, sample_rate).into_iter().flatten().collect())
+}
+
+fn decode_wav(data: &[u8]) -> Result<(usize, u32, Vec<f32>), hound::Error> {
+    let mut reader = hound::WavReader::new(Cursor::new(data))?;
+    let spec = reader.spec();
+    let samples = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
+        hound::SampleFormat::Int => {
+            let scale = full_scale(spec.bits_per_sample as u32);
+            reader.samples::<i32>().map(|s| s.map(|s| s as f32 / scale)).collect::<Result<_, _>>()?
+        },
+    };
+    Ok((spec.channels as usize, spec.sample_rate, samples))
+}
+
+fn decode_flac(data: &[u8]) -> Result<(usize, u32, Vec<f32>), claxon::Error> {
+    let mut reader = claxon::FlacReader::new(Cursor::new(data))?;
+    let info = reader.streaminfo();
+    let scale = full_scale(info.bits_per_sample);
+    let samples = reader.samples().map(|s| s.map(|s| s as f32 / scale)).collect::<Result<_, _>>()?;
+    Ok((info.channels as usize, info.sample_rate, samples))
+}
+
+fn decode_vorbis(data: &[u8]) -> Result<(usize, u32, Vec<f32>), lewton::VorbisError> {
+    let mut reader = lewton::inside_ogg::OggStreamReader::new(Cursor::new(data))?;
+    let mut samples = vec![];
+    while let Some(packet) = reader.read_dec_packet_itl()? {
+        samples.extend(packet.into_iter().map(|s| s as f32 / 32768.0));
+    }
+    Ok((reader.ident_hdr.audio_channels as usize, reader.ident_hdr.audio_sample_rate, samples))
+}
+
+/// The magnitude of the most negative integer sample with this many bits.
+fn full_scale(bits: u32) -> f32 {
+    (1u64 << bits.clamp(1, 32).saturating_sub(1)) as f32
+}
+
+/// Changes the sample rate by interpolating linearly between frames.
+fn resample(frames: &[[f32; 2]], from: u32, to: u32) -> Vec<[f32; 2]> {
+    if from == to || frames.is_empty() {
+        return frames.to_vec();
+    }
+    let len = (frames.len() as u64 * to as u64 / from as u64) as usize;
+    (0..len).map(|i| {
+        let position = i as f64 * from as f64 / to as f64;
+        let index = position as usize;
+        let fraction = (position - index as f64) as f32;
+        let a = frames[index];
+        let b = frames.get(index + 1).unwrap_or(&a);
+        [a[0] + (b[0] - a[0]) * fraction, a[1] + (b[1] - a[1]) * fraction]
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wav(channels: u16, sample_rate: u32, samples: &[i16]) -> Vec<u8> {
+        let spec = hound::WavSpec {
+            channels,
+            sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut data = Cursor::new(vec![]);
+        let mut writer = hound::WavWriter::new(&mut data, spec).unwrap();
+        for s in samples {
+            writer.write_sample(*s).unwrap();
+        }
+        writer.finalize().unwrap();
+        data.into_inner()
+    }
+
+    #[test]
+    fn mono_is_played_on_both_sides() {
+        let samples = decode(&wav(1, 44100, &[0, 16384, -32768]), 44100).unwrap();
+        assert_eq!(samples, vec![0.0, 0.0, 0.5, 0.5, -1.0, -1.0]);
+    }
+
+    #[test]
+    fn extra_channels_are_dropped() {
+        let samples = decode(&wav(3, 44100, &[16384, -16384, 32767]), 44100).unwrap();
+        assert_eq!(samples, vec![0.5, -0.5]);
+    }
+
+    #[test]
+    fn resamples_linearly() {
+        let samples = decode(&wav(2, 22050, &[0, 0, 16384, -16384]), 44100).unwrap();
+        assert_eq!(samples, vec![0.0, 0.0, 0.25, -0.25, 0.5, -0.5, 0.5, -0.5]);
+    }
+
+    #[test]
+    fn unrecognized_data() {
+        assert!(matches!(decode(b"not audio at all", 44100), Err(DecodeError::UnrecognizedFormat)));
+        assert!(matches!(decode(b"fLaC and then nonsense", 44100), Err(DecodeError::Invalid("FLAC", _))));
+    }
+}
@@ -0,0 +1,267 @@
+use {
+    std::{
+        io,
+        time::Instant,
+    },
+
+    crate::{ControlMap, Value},
+    super::{AudioBackend, Trigger, SampleBank, Diagnostic},
+};
+
+/// A channel voice message. Channels are numbered from zero.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MidiMessage {
+    NoteOff { channel: u8, note: u8, velocity: u8 },
+    NoteOn { channel: u8, note: u8, velocity: u8 },
+    ControlChange { channel: u8, controller: u8, value: u8 },
+}
+
+impl MidiMessage {
+    /// The message as it's sent over the wire.
+    pub fn to_bytes(self) -> [u8; 3] {
+        match self {
+            MidiMessage::NoteOff { channel, note, velocity } => [0x80 | channel, note, velocity],
+            MidiMessage::NoteOn { channel, note, velocity } => [0x90 | channel, note, velocity],
+            MidiMessage::ControlChange { channel, controller, value } => [0xB0 | channel, controller, value],
+        }
+    }
+}
+
+/// Somewhere to send MIDI messages, such as a hardware port.
+pub trait MidiOutput: Send + 'static {
+    /// Sends a message which should take effect at `time`, in seconds on the
+    /// `MidiBackend`'s clock. Messages are sent ahead of time and aren't
+    /// necessarily in time order: a note's note-off is sent along with its note-on.
+    fn send(&mut self, time: f64, message: MidiMessage) -> io::Result<()>;
+}
+
+/// Lets a `MidiBackend` send to an output chosen at runtime, as a `Box<dyn MidiOutput>`.
+impl<O: MidiOutput + ?Sized> MidiOutput for Box<O> {
+    fn send(&mut self, time: f64, message: MidiMessage) -> io::Result<()> {
+        (**self).send(time, message)
+    }
+}
+
+/// A `MidiOutput` which keeps every message it's sent.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryMidiOutput {
+    messages: Vec<(f64, MidiMessage)>,
+}
+
+impl MemoryMidiOutput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The messages received so far, sorted by time.
+    pub fn messages(&mut self) -> &[(f64, MidiMessage)] {
+        self.messages.sort_by(|a, b| a.0.total_cmp(&b.0));
+        &self.messages
+    }
+
+    /// Removes and returns the messages received so far, sorted by time.
+    pub fn take_messages(&mut self) -> Vec<(f64, MidiMessage)> {
+        self.messages();
+        std::mem::take(&mut self.messages)
+    }
+}
+
+impl MidiOutput for MemoryMidiOutput {
+    fn send(&mut self, time: f64, message: MidiMessage) -> io::Result<()> {
+        self.messages.push((time, message));
+        Ok(())
+    }
+}
+
+/// Turns events into MIDI messages. The controls it understands follow SuperDirt's MIDI support:
+///
+/// * `note` - semitones relative to middle C, so `note` 0 is MIDI note 60.
+/// * `velocity` - from 0 to 1, defaulting to 1.
+/// * `midichan` - from 0 to 15, defaulting to 0.
+/// * `ccn` and `ccv` - a control change number and value, sent when the event starts.
+/// * `sustain` - how long the note lasts in seconds.
+/// * `legato` - how long the note lasts relative to the event, used when there's no `sustain`.
+///
+/// Without either of the last two a note lasts as long as the event's whole.
+pub struct MidiBackend<O: MidiOutput> {
+    output: O,
+    started: Instant,
+}
+
+impl<O: MidiOutput> MidiBackend<O> {
+    pub fn new(output: O) -> Self {
+        Self {
+            output,
+            started: Instant::now(),
+        }
+    }
+
+    pub fn output(&mut self) -> &mut O {
+        &mut self.output
+    }
+
+    pub fn into_output(self) -> O {
+        self.output
+    }
+}
+
+impl<O: MidiOutput> AudioBackend for MidiBackend<O> {
+    fn sample_rate(&self) -> Option<u32> {
+        None
+    }
+
+    fn now(&self) -> f64 {
+        self.started.elapsed().as_secs_f64()
+    }
+
+    fn trigger(&mut self, trigger: &Trigger, _samples: &SampleBank) -> Result<(), Diagnostic> {
+        for (time, message) in midi_messages(&trigger.value, trigger.time, trigger.delta, 1.0) {
+            self.output.send(time, message).map_err(|e| Diagnostic::Send(e.to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+/// The timestamped messages for an event, using the mapping described on
+/// `MidiBackend`. `time` and `delta` can be in any unit as long as there are
+/// `per_second` of them in a second.
+pub(super) fn midi_messages(controls: &ControlMap, time: f64, delta: f64, per_second: f64) -> Vec<(f64, MidiMessage)> {
+    let channel = get(controls, "midichan").map_or(0, |c| c.clamp(0.0, 15.0) as u8);
+    let mut messages = vec![];
+
+    if let (Some(controller), Some(value)) = (get(controls, "ccn"), get(controls, "ccv")) {
+        messages.push((time, MidiMessage::ControlChange {
+            channel,
+            controller: to_7bit(controller),
+            value: to_7bit(value),
+        }));
+    }
+
+    if let Some(note) = get(controls, "note") {
+        let note = to_7bit((note + 60.0).round());
+        let velocity = to_7bit((get(controls, "velocity").unwrap_or(1.0) * 127.0).round());
+        let duration = match (get(controls, "sustain"), get(controls, "legato")) {
+            (Some(sustain), _) => sustain * per_second,
+            (None, Some(legato)) => delta * legato,
+            (None, None) => delta,
+        };
+        messages.push((time, MidiMessage::NoteOn { channel, note, velocity }));
+        messages.push((time + duration.max(0.0), MidiMessage::NoteOff { channel, note, velocity: 0 }));
+    }
+
+    messages
+}
+
+fn get(controls: &ControlMap, key: &str) -> Option<f64> {
+    controls.0.get(key).and_then(Value::as_f64)
+}
+
+fn to_7bit(value: f64) -> u8 {
+    value.clamp(0.0, 127.0) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use MidiMessage::{NoteOn, NoteOff, ControlChange};
+
+    /// Triggers one event with the controls, starting at one second and lasting half a second.
+    fn messages(controls: &[(&str, Value)]) -> Vec<(f64, MidiMessage)> {
+        let mut backend = MidiBackend::new(MemoryMidiOutput::new());
+        let trigger = Trigger {
+            time: 1.0,
+            cycle: 0.into(),
+            delta: 0.5,
+            cps: 1.0,
+            value: ControlMap(controls.iter().map(|(k, v)| (k.to_string(), v.clone())).collect::<HashMap<_, _>>()),
+        };
+        backend.trigger(&trigger, &SampleBank::new()).unwrap();
+        backend.into_output().take_messages()
+    }
+
+    #[test]
+    fn note_lasts_for_the_event() {
+        assert_eq!(messages(&[("note", Value::Float(0.0))]), vec![
+            (1.0, NoteOn { channel: 0, note: 60, velocity: 127 }),
+            (1.5, NoteOff { channel: 0, note: 60, velocity: 0 }),
+        ]);
+    }
+
+    #[test]
+    fn velocity_and_channel() {
+        assert_eq!(messages(&[("note", Value::Integer(7)), ("velocity", Value::Float(0.5)), ("midichan", Value::Float(2.0))]), vec![
+            (1.0, NoteOn { channel: 2, note: 67, velocity: 64 }),
+            (1.5, NoteOff { channel: 2, note: 67, velocity: 0 }),
+        ]);
+    }
+
+    #[test]
+    fn legato_scales_the_event() {
+        assert_eq!(messages(&[("note", Value::Float(-12.0)), ("legato", Value::Float(0.5))]), vec![
+            (1.0, NoteOn { channel: 0, note: 48, velocity: 127 }),
+            (1.25, NoteOff { channel: 0, note: 48, velocity: 0 }),
+        ]);
+    }
+
+    #[test]
+    fn sustain_is_in_seconds_and_beats_legato() {
+        assert_eq!(messages(&[("note", Value::Float(0.0)), ("sustain", Value::Float(2.0)), ("legato", Value::Float(0.5))]), vec![
+            (1.0, NoteOn { channel: 0, note: 60, velocity: 127 }),
+            (3.0, NoteOff { channel: 0, note: 60, velocity: 0 }),
+        ]);
+    }
+
+    #[test]
+    fn control_change_without_a_note() {
+        assert_eq!(messages(&[("ccn", Value::Float(74.0)), ("ccv", Value::Integer(100)), ("midichan", Value::Float(1.0))]), vec![
+            (1.0, ControlChange { channel: 1, controller: 74, value: 100 }),
+        ]);
+        assert_eq!(messages(&[("ccn", Value::Float(74.0))]), vec![]);
+    }
+
+    #[test]
+    fn values_are_clamped_to_midi_ranges() {
+        assert_eq!(messages(&[("note", Value::Float(100.0)), ("velocity", Value::Float(2.0)), ("midichan", Value::Float(20.0)), ("ccn", Value::Float(-1.0)), ("ccv", Value::Float(200.0))]), vec![
+            (1.0, ControlChange { channel: 15, controller: 0, value: 127 }),
+            (1.0, NoteOn { channel: 15, note: 127, velocity: 127 }),
+            (1.5, NoteOff { channel: 15, note: 127, velocity: 0 }),
+        ]);
+    }
+
+    #[test]
+    fn message_bytes() {
+        assert_eq!(NoteOn { channel: 2, note: 60, velocity: 100 }.to_bytes(), [0x92, 60, 100]);
+        assert_eq!(NoteOff { channel: 0, note: 60, velocity: 0 }.to_bytes(), [0x80, 60, 0]);
+        assert_eq!(ControlChange { channel: 15, controller: 74, value: 1 }.to_bytes(), [0xBF, 74, 1]);
+    }
+
+    /// Keeps the messages somewhere the test can still see once it's boxed up.
+    struct Shared(std::sync::Arc<std::sync::Mutex<MemoryMidiOutput>>);
+
+    impl MidiOutput for Shared {
+        fn send(&mut self, time: f64, message: MidiMessage) -> io::Result<()> {
+            self.0.lock().unwrap().send(time, message)
+        }
+    }
+
+    #[test]
+    fn boxed_outputs() {
+        let memory = std::sync::Arc::new(std::sync::Mutex::new(MemoryMidiOutput::new()));
+        let output: Box<dyn MidiOutput> = Box::new(Shared(memory.clone()));
+        let mut backend = MidiBackend::new(output);
+        let trigger = Trigger {
+            time: 1.0,
+            cycle: 0.into(),
+            delta: 0.5,
+            cps: 1.0,
+            value: ControlMap([("note".to_string(), Value::Float(0.0))].iter().cloned().collect()),
+        };
+        backend.trigger(&trigger, &SampleBank::new()).unwrap();
+        assert_eq!(memory.lock().unwrap().take_messages(), vec![
+            (1.0, NoteOn { channel: 0, note: 60, velocity: 127 }),
+            (1.5, NoteOff { channel: 0, note: 60, velocity: 0 }),
+        ]);
+    }
+}
@@ -0,0 +1,247 @@
+use {
+    std::{
+        io,
+        net::{UdpSocket, ToSocketAddrs},
+        time::{Instant, SystemTime, UNIX_EPOCH},
+    },
+
+    crate::{Value, time_to_f64},
+    super::{AudioBackend, Trigger, SampleBank, Diagnostic},
+};
+
+/// Seconds between the NTP epoch (1900) used by OSC timetags and the Unix epoch.
+const NTP_UNIX_OFFSET: f64 = 2_208_988_800.0;
+/// The same default SuperDirt and Tidal use.
+pub const SUPERDIRT_PORT: u16 = 57120;
+
+/// Sends events over UDP as timestamped OSC bundles in SuperDirt's format,
+/// so that an existing SuperDirt install can play them.
+pub struct OscBackend {
+    socket: UdpSocket,
+    address: String,
+    latency: f64,
+    /// When the clock started, both monotonically and as wall-clock time in seconds since the Unix epoch.
+    started: Instant,
+    started_unix: f64,
+}
+
+impl OscBackend {
+    /// Sends `/dirt/play` messages to `target`, usually `("127.0.0.1", SUPERDIRT_PORT)`.
+    pub fn new(target: impl ToSocketAddrs) -> io::Result<Self> {
+        let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+        socket.connect(target)?;
+        Ok(Self {
+            socket,
+            address: "/dirt/play".to_string(),
+            latency: 0.2,
+            started: Instant::now(),
+            started_unix: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs_f64()).unwrap_or(0.0),
+        })
+    }
+
+    /// Sets the OSC address messages are sent to, for instance `/play2` for older SuperDirt versions.
+    pub fn with_address(mut self, address: impl Into<String>) -> Self {
+        self.address = address.into();
+        self
+    }
+
+    /// Sets how far in the future, in seconds, bundles are timestamped. This
+    /// gives the receiver time to handle them before they're due. Defaults to 0.2.
+    pub fn with_latency(mut self, latency: f64) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// The `/dirt/play` message for the trigger. The `cps`, `cycle` and
+    /// `delta` the scheduler worked out replace any controls of the same name.
+    fn message(&self, trigger: &Trigger) -> Vec<u8> {
+        let mut args: Vec<(&str, OscArg)> = trigger.value.0.iter().filter(|(k, _)| {
+            !matches!(k.as_str(), "cps" | "cycle" | "delta")
+        }).map(|(k, v)| {
+            let v = match v {
+                Value::String(s) => OscArg::String(s),
+                Value::Integer(i) => OscArg::Int(*i as i32),
+                Value::Float(f) => OscArg::Float(*f),
+            };
+            (k.as_str(), v)
+        }).collect();
+        args.sort_by_key(|(k, _)| *k);
+        args.push(("cps", OscArg::Float(trigger.cps as f32)));
+        args.push(("cycle", OscArg::Float(time_to_f64(trigger.cycle) as f32)));
+        args.push(("delta", OscArg::Float(trigger.delta as f32)));
+
+        let mut tags = ",".to_string();
+        let mut data = vec![];
+        for (key, value) in args {
+            tags.push('s');
+            write_string(&mut data, key);
+            match value {
+                OscArg::Int(i) => {
+                    tags.push('i');
+                    data.extend_from_slice(&i.to_be_bytes());
+                },
+                OscArg::Float(f) => {
+                    tags.push('f');
+                    data.extend_from_slice(&f.to_be_bytes());
+                },
+                OscArg::String(s) => {
+                    tags.push('s');
+                    write_string(&mut data, s);
+                },
+            }
+        }
+
+        let mut message = vec![];
+        write_string(&mut message, &self.address);
+        write_string(&mut message, &tags);
+        message.extend(data);
+        message
+    }
+
+    fn bundle(&self, time: f64, message: &[u8]) -> Vec<u8> {
+        let mut bundle = vec![];
+        write_string(&mut bundle, "#bundle");
+        bundle.extend_from_slice(&timetag(self.started_unix + time + self.latency).to_be_bytes());
+        bundle.extend_from_slice(&(message.len() as i32).to_be_bytes());
+        bundle.extend_from_slice(message);
+        bundle
+    }
+}
+
+impl AudioBackend for OscBackend {
+    fn sample_rate(&self) -> Option<u32> {
+        None
+    }
+
+    fn now(&self) -> f64 {
+        self.started.elapsed().as_secs_f64()
+    }
+
+    fn trigger(&mut self, trigger: &Trigger, _samples: &SampleBank) -> Result<(), Diagnostic> {
+        let bundle = self.bundle(trigger.time, &self.message(trigger));
+        self.socket.send(&bundle).map(|_| ()).map_err(|e| Diagnostic::Send(e.to_string()))
+    }
+}
+
+enum OscArg<'a> {
+    Int(i32),
+    Float(f32),
+    String(&'a str),
+}
+
+/// Writes a null terminated string padded to a multiple of four bytes.
+fn write_string(buf: &mut Vec<u8>, s: &str) {
+    buf.extend_from_slice(s.as_bytes());
+    let padding = 4 - s.len() % 4;
+    buf.extend(std::iter::repeat_n(0, padding));
+}
+
+/// Converts seconds since the Unix epoch to a 64 bit NTP fixed point timestamp.
+fn timetag(unix: f64) -> u64 {
+    let ntp = unix + NTP_UNIX_OFFSET;
+    let seconds = ntp.floor();
+    let fraction = ((ntp - seconds) * (1u64 << 32) as f64) as u64;
+    ((seconds as u64) << 32) | fraction.min(u32::MAX as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use super::*;
+    use crate::{mini_notation::parse_pattern, merge, gain, ControlMap, sound::Player};
+
+    /// Reads a null terminated string and its padding.
+    fn read_string(buf: &[u8], pos: &mut usize) -> String {
+        let len = buf[*pos..].iter().position(|b| *b == 0).unwrap();
+        let s = String::from_utf8(buf[*pos..*pos + len].to_vec()).unwrap();
+        assert!(buf[*pos + len..*pos + (len / 4 + 1) * 4].iter().all(|b| *b == 0));
+        *pos += (len / 4 + 1) * 4;
+        s
+    }
+
+    fn read_4(buf: &[u8], pos: &mut usize) -> [u8; 4] {
+        *pos += 4;
+        buf[*pos - 4..*pos].try_into().unwrap()
+    }
+
+    #[test]
+    fn write_string_pads_to_four_bytes() {
+        let mut buf = vec![];
+        write_string(&mut buf, "abc");
+        assert_eq!(buf, b"abc\0");
+        let mut buf = vec![];
+        write_string(&mut buf, "gain");
+        assert_eq!(buf, b"gain\0\0\0\0");
+    }
+
+    #[test]
+    fn sends_dirt_play_bundles() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_read_timeout(Some(std::time::Duration::from_secs(5))).unwrap();
+        let created = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs_f64();
+        let backend = OscBackend::new(socket.local_addr().unwrap()).unwrap().with_latency(0.2);
+        let player = Player::with_backend(crate::sound::SampleBank::new(), backend);
+        player.set_pattern("d1", merge(crate::sound(parse_pattern("bd:3")), gain(parse_pattern("1.5"))));
+        player.tick();
+        std::thread::sleep(std::time::Duration::from_millis(150));
+        player.tick();
+
+        let mut buf = [0; 1024];
+        let len = socket.recv(&mut buf).unwrap();
+        let buf = &buf[..len];
+        let mut pos = 0;
+        assert_eq!(read_string(buf, &mut pos), "#bundle");
+
+        // The first event is due a lookahead after the first tick, plus the latency.
+        let timetag = u64::from_be_bytes(buf[pos..pos + 8].try_into().unwrap());
+        pos += 8;
+        let time = (timetag >> 32) as f64 + (timetag & 0xFFFF_FFFF) as f64 / (1u64 << 32) as f64 - NTP_UNIX_OFFSET;
+        assert!((time - (created + 0.3)).abs() < 0.05, "timetag {} for a backend created at {}", time, created);
+
+        let size = i32::from_be_bytes(read_4(buf, &mut pos)) as usize;
+        assert_eq!(size, len - pos);
+        assert_eq!(read_string(buf, &mut pos), "/dirt/play");
+        assert_eq!(read_string(buf, &mut pos), ",sfsisssfsfsf");
+
+        assert_eq!(read_string(buf, &mut pos), "gain");
+        assert_eq!(f32::from_be_bytes(read_4(buf, &mut pos)), 1.5);
+        assert_eq!(read_string(buf, &mut pos), "n");
+        assert_eq!(i32::from_be_bytes(read_4(buf, &mut pos)), 3);
+        assert_eq!(read_string(buf, &mut pos), "s");
+        assert_eq!(read_string(buf, &mut pos), "bd");
+        assert_eq!(read_string(buf, &mut pos), "cps");
+        assert_eq!(f32::from_be_bytes(read_4(buf, &mut pos)), 0.5);
+        assert_eq!(read_string(buf, &mut pos), "cycle");
+        assert_eq!(f32::from_be_bytes(read_4(buf, &mut pos)), 0.0);
+        assert_eq!(read_string(buf, &mut pos), "delta");
+        assert_eq!(f32::from_be_bytes(read_4(buf, &mut pos)), 2.0);
+        assert_eq!(pos, len);
+    }
+
+    #[test]
+    fn cps_controls_are_sent_once() {
+        let backend = OscBackend::new("127.0.0.1:9").unwrap();
+        let trigger = Trigger {
+            time: 0.0,
+            cycle: 2.into(),
+            delta: 0.5,
+            cps: 2.0,
+            value: ControlMap([("s", Value::String("bd".to_string())), ("cps", Value::Float(2.0)), ("delta", Value::Float(9.0))]
+                .iter().map(|(k, v)| (k.to_string(), v.clone())).collect()),
+        };
+        let message = backend.message(&trigger);
+        let mut pos = 0;
+        assert_eq!(read_string(&message, &mut pos), "/dirt/play");
+        assert_eq!(read_string(&message, &mut pos), ",sssfsfsf");
+        assert_eq!(read_string(&message, &mut pos), "s");
+        assert_eq!(read_string(&message, &mut pos), "bd");
+        assert_eq!(read_string(&message, &mut pos), "cps");
+        assert_eq!(f32::from_be_bytes(read_4(&message, &mut pos)), 2.0);
+        assert_eq!(read_string(&message, &mut pos), "cycle");
+        assert_eq!(f32::from_be_bytes(read_4(&message, &mut pos)), 2.0);
+        assert_eq!(read_string(&message, &mut pos), "delta");
+        assert_eq!(f32::from_be_bytes(read_4(&message, &mut pos)), 0.5);
+        assert_eq!(pos, message.len());
+    }
+}
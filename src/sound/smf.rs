@@ -0,0 +1,180 @@
+use {
+    std::{
+        convert::TryInto,
+        io,
+        path::Path,
+    },
+
+    crate::{Event, Pattern, ControlMap, Arc, Time, time_to_f64},
+    super::{MidiMessage, midi::midi_messages},
+};
+
+/// Ticks per quarter note. A cycle is written as a bar of four quarter notes.
+const TICKS_PER_QUARTER: u16 = 960;
+const TICKS_PER_CYCLE: f64 = TICKS_PER_QUARTER as f64 * 4.0;
+
+/// Renders `cycles` of each named pattern as a type 1 Standard MIDI File,
+/// returning its bytes. The first track holds the tempo, starting at `cps`
+/// and following any `cps` controls. Each pattern then gets a track of its
+/// own, named after it, with controls mapped as described on `MidiBackend`.
+///
+/// Only events which start within `cycles` are written, so an event split
+/// into fragments becomes a single note covering its whole.
+pub fn render_midi<'a, S: AsRef<str>>(patterns: impl IntoIterator<Item = (S, &'a Pattern<ControlMap>)>, cps: f64, cycles: Arc) -> Vec<u8> {
+    let tracks: Vec<(S, Vec<Event<ControlMap>>)> = patterns.into_iter().map(|(name, pattern)| {
+        let mut events = pattern(cycles);
+        events.retain(|e| e.has_onset());
+        events.sort_by_key(|e| e.part.start);
+        (name, events)
+    }).collect();
+
+    let mut tempo_changes: Vec<(Time, f64)> = tracks.iter()
+        .flat_map(|(_, events)| events)
+        .filter_map(|e| {
+            let cps: f32 = e.value.0.get("cps")?.clone().try_into().ok()?;
+            Some((e.part.start, cps as f64))
+        })
+        .filter(|(_, cps)| cps.is_finite() && *cps > 0.0)
+        .collect();
+    tempo_changes.sort_by_key(|(cycle, _)| *cycle);
+    let cps_at = |cycle: Time| tempo_changes.iter().take_while(|(c, _)| *c <= cycle).last().map_or(cps, |(_, cps)| *cps);
+
+    let mut tempo_track = vec![(0, tempo_event(cps))];
+    tempo_track.extend(tempo_changes.iter().map(|(cycle, cps)| (to_ticks(*cycle - cycles.start), tempo_event(*cps))));
+
+    let mut file = vec![];
+    file.extend_from_slice(b"MThd");
+    file.extend_from_slice(&6u32.to_be_bytes());
+    file.extend_from_slice(&1u16.to_be_bytes());
+    file.extend_from_slice(&(tracks.len() as u16 + 1).to_be_bytes());
+    file.extend_from_slice(&TICKS_PER_QUARTER.to_be_bytes());
+    write_track(&mut file, tempo_track);
+
+    for (name, events) in tracks {
+        let mut messages = vec![(0, 0, meta_event(0x03, name.as_ref().as_bytes()))];
+        for event in events {
+            let whole = event.whole_or_part();
+            let start = time_to_f64(whole.start - cycles.start);
+            let delta = time_to_f64(whole.stop - whole.start);
+            for (time, message) in midi_messages(&event.value, start, delta, cps_at(whole.start)) {
+                // Note-offs go first so that a note ending where the same note
+                // starts again doesn't cut the new one short.
+                let order = match message {
+                    MidiMessage::NoteOff { .. } => 1,
+                    MidiMessage::ControlChange { .. } => 2,
+                    MidiMessage::NoteOn { .. } => 3,
+                };
+                messages.push(((time * TICKS_PER_CYCLE).round() as u64, order, message.to_bytes().to_vec()));
+            }
+        }
+        messages.sort_by_key(|(tick, order, _)| (*tick, *order));
+        write_track(&mut file, messages.into_iter().map(|(tick, _, data)| (tick, data)).collect());
+    }
+
+    file
+}
+
+/// Renders `cycles` of each named pattern to a Standard MIDI File. See `render_midi`.
+pub fn render_to_midi_file<'a, S: AsRef<str>>(path: impl AsRef<Path>, patterns: impl IntoIterator<Item = (S, &'a Pattern<ControlMap>)>, cps: f64, cycles: Arc) -> io::Result<()> {
+    std::fs::write(path, render_midi(patterns, cps, cycles))
+}
+
+fn to_ticks(cycles: Time) -> u64 {
+    (time_to_f64(cycles) * TICKS_PER_CYCLE).round() as u64
+}
+
+/// A set tempo meta event, which measures tempo in microseconds per quarter note.
+fn tempo_event(cps: f64) -> Vec<u8> {
+    let micros = (1_000_000.0 / (cps * 4.0)).round().clamp(1.0, 0xFF_FFFF as f64) as u32;
+    meta_event(0x51, &micros.to_be_bytes()[1..])
+}
+
+fn meta_event(kind: u8, data: &[u8]) -> Vec<u8> {
+    let mut event = vec![0xFF, kind];
+    write_variable_length(&mut event, data.len() as u64);
+    event.extend_from_slice(data);
+    event
+}
+
+/// Writes a track chunk from events sorted by their absolute tick.
+fn write_track(file: &mut Vec<u8>, events: Vec<(u64, Vec<u8>)>) {
+    let mut track = vec![];
+    let mut last = 0;
+    for (tick, data) in events {
+        write_variable_length(&mut track, tick - last);
+        track.extend(data);
+        last = tick;
+    }
+    write_variable_length(&mut track, 0);
+    track.extend(meta_event(0x2F, &[]));
+
+    file.extend_from_slice(b"MTrk");
+    file.extend_from_slice(&(track.len() as u32).to_be_bytes());
+    file.extend(track);
+}
+
+/// Writes a number seven bits at a time, most significant first, with the top
+/// bit set on every byte but the last.
+fn write_variable_length(buf: &mut Vec<u8>, mut value: u64) {
+    let mut bytes = vec![(value & 0x7F) as u8];
+    value >>= 7;
+    while value > 0 {
+        bytes.push((value & 0x7F) as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.extend(bytes.into_iter().rev());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mini_notation::parse_pattern, note};
+
+    #[test]
+    fn variable_length_quantities() {
+        for (value, bytes) in [(0, vec![0x00]), (0x7F, vec![0x7F]), (0x80, vec![0x81, 0x00]), (3840, vec![0x9E, 0x00]), (0x0FFF_FFFF, vec![0xFF, 0xFF, 0xFF, 0x7F])].iter() {
+            let mut buf = vec![];
+            write_variable_length(&mut buf, *value);
+            assert_eq!(&buf, bytes);
+        }
+    }
+
+    #[test]
+    fn fragments_are_written_once() {
+        // Each note lasts two cycles. Starting from cycle 1 cuts the first
+        // note 0 in half, so it's left out, while note 7 is written once even
+        // though it spans two cycles.
+        let p = note(parse_pattern("0 7")).slow(parse_pattern("4"));
+        let file = render_midi(vec![("d1", &p)], 0.5, Arc { start: 1.into(), stop: 5.into() });
+        let expected: Vec<u8> = [
+            &b"MThd"[..], &[0, 0, 0, 6], &[0, 1], &[0, 2], &[0x03, 0xC0],
+            b"MTrk", &[0, 0, 0, 11],
+            // 500000 microseconds per quarter note is a cycle every two seconds.
+            &[0x00, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20],
+            &[0x00, 0xFF, 0x2F, 0x00],
+            b"MTrk", &[0, 0, 0, 29],
+            &[0x00, 0xFF, 0x03, 0x02], b"d1",
+            &[0x9E, 0x00, 0x90, 67, 127],
+            &[0xBC, 0x00, 0x80, 67, 0],
+            &[0x00, 0x90, 60, 127],
+            &[0xBC, 0x00, 0x80, 60, 0],
+            &[0x00, 0xFF, 0x2F, 0x00],
+        ].concat();
+        assert_eq!(file, expected);
+    }
+
+    #[test]
+    fn cps_controls_add_tempo_changes() {
+        let p = crate::merge(note(parse_pattern("0")), crate::cps(parse_pattern("<0.5 1>")));
+        let file = render_midi(vec![("d1", &p)], 0.5, Arc { start: 0.into(), stop: 2.into() });
+        let tempo_track: Vec<u8> = [
+            &b"MTrk"[..], &[0, 0, 0, 26],
+            &[0x00, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20],
+            &[0x00, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20],
+            // 250000 microseconds per quarter note from the second cycle.
+            &[0x9E, 0x00, 0xFF, 0x51, 0x03, 0x03, 0xD0, 0x90],
+            &[0x00, 0xFF, 0x2F, 0x00],
+        ].concat();
+        assert_eq!(&file[14..14 + tempo_track.len()], &tempo_track[..]);
+    }
+}